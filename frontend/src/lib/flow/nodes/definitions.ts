@@ -6,6 +6,10 @@ export type NodeKind =
 	| 'trigger-webhook'
 	| 'chat-trigger'
 	| 'slack-trigger'
+	| 'postgres-trigger'
+	| 'local-file-trigger'
+	| 'error-trigger'
+	| 'form-trigger'
 	// Logic/Flow Control
 	| 'if'
 	| 'switch'
@@ -20,6 +24,7 @@ export type NodeKind =
 	| 'rss-feed-read'
 	| 'slack'
 	| 'data-table'
+	| 'execute-workflow'
 	| 'convert-to-file'
 	| 'extract-from-file'
 	| 'read-write-file'
@@ -30,13 +35,18 @@ export type NodeKind =
 	| 'openrouter'
 	| 'tool'
 	| 'rss-read-tool'
+	| 'memory-window'
+	| 'memory-token-buffer'
+	| 'memory-summary'
+	| 'knowledge-retrieval'
+	| 'embeddings'
 	// End
 	| 'trigger-end';
 
 export type NodePort = {
 	id: string;
 	label: string;
-	type?: 'data' | 'tool';
+	type?: 'data' | 'tool' | 'memory';
 };
 
 export type NodeDefinition = {
@@ -125,6 +135,50 @@ export const triggerNodes: NodeDefinition[] = [
 		minHeight: 80,
 		inputs: [],
 		outputs: [{ id: 'out', label: '' }]
+	},
+	{
+		kind: 'postgres-trigger',
+		label: 'Postgres Trigger',
+		detail: 'Listens for NOTIFY or new rows in Postgres',
+		tone: 'emerald',
+		icon: 'PT',
+		width: 220,
+		minHeight: 80,
+		inputs: [],
+		outputs: [{ id: 'out', label: '' }]
+	},
+	{
+		kind: 'local-file-trigger',
+		label: 'Local File Trigger',
+		detail: 'Triggers when files change in a folder',
+		tone: 'emerald',
+		icon: 'LF',
+		width: 220,
+		minHeight: 80,
+		inputs: [],
+		outputs: [{ id: 'out', label: '' }]
+	},
+	{
+		kind: 'error-trigger',
+		label: 'Error Trigger',
+		detail: 'Runs when another workflow fails',
+		tone: 'emerald',
+		icon: 'ER',
+		width: 220,
+		minHeight: 80,
+		inputs: [],
+		outputs: [{ id: 'out', label: '' }]
+	},
+	{
+		kind: 'form-trigger',
+		label: 'Form Trigger',
+		detail: 'Starts workflow from a hosted form',
+		tone: 'emerald',
+		icon: 'FM',
+		width: 220,
+		minHeight: 80,
+		inputs: [],
+		outputs: [{ id: 'out', label: '' }]
 	}
 ];
 
@@ -289,6 +343,17 @@ export const transformNodes: NodeDefinition[] = [
 		minHeight: 80,
 		inputs: [{ id: 'in', label: '' }],
 		outputs: [{ id: 'out', label: '' }]
+	},
+	{
+		kind: 'execute-workflow',
+		label: 'Execute Workflow',
+		detail: 'Runs another workflow',
+		tone: 'cobalt',
+		icon: 'EW',
+		width: 200,
+		minHeight: 80,
+		inputs: [{ id: 'in', label: '' }],
+		outputs: [{ id: 'out', label: '' }]
 	}
 ];
 
@@ -341,7 +406,8 @@ export const aiNodes: NodeDefinition[] = [
 		minHeight: 100,
 		inputs: [
 			{ id: 'in', label: '' },
-			{ id: 'tools', label: 'Tools', type: 'tool' }
+			{ id: 'tools', label: 'Tools', type: 'tool' },
+			{ id: 'memory', label: 'Memory', type: 'memory' }
 		],
 		outputs: [{ id: 'out', label: '' }]
 	},
@@ -386,6 +452,31 @@ export const aiNodes: NodeDefinition[] = [
 			{ id: 'out', label: '' },
 			{ id: 'error', label: 'error' }
 		]
+	},
+	{
+		kind: 'embeddings',
+		label: 'Embeddings',
+		detail: 'Turns text into vectors',
+		tone: 'cobalt',
+		icon: 'EM',
+		width: 220,
+		minHeight: 80,
+		inputs: [{ id: 'in', label: '' }],
+		outputs: [
+			{ id: 'out', label: '' },
+			{ id: 'error', label: 'error' }
+		]
+	},
+	{
+		kind: 'knowledge-retrieval',
+		label: 'Knowledge Retrieval',
+		detail: 'Finds relevant chunks in a knowledge base',
+		tone: 'cobalt',
+		icon: 'KR',
+		width: 220,
+		minHeight: 80,
+		inputs: [{ id: 'in', label: '' }],
+		outputs: [{ id: 'out', label: '' }]
 	}
 ];
 
@@ -412,6 +503,39 @@ export const toolNodes: NodeDefinition[] = [
 		minHeight: 60,
 		inputs: [],
 		outputs: [{ id: 'tool', label: '', type: 'tool' }]
+	},
+	{
+		kind: 'memory-window',
+		label: 'Window Buffer Memory',
+		detail: 'Remembers the last N interactions',
+		tone: 'amber',
+		icon: 'WM',
+		width: 180,
+		minHeight: 60,
+		inputs: [],
+		outputs: [{ id: 'memory', label: '', type: 'memory' }]
+	},
+	{
+		kind: 'memory-token-buffer',
+		label: 'Token Buffer Memory',
+		detail: 'Remembers as much as fits in a token budget',
+		tone: 'amber',
+		icon: 'TM',
+		width: 180,
+		minHeight: 60,
+		inputs: [],
+		outputs: [{ id: 'memory', label: '', type: 'memory' }]
+	},
+	{
+		kind: 'memory-summary',
+		label: 'Summary Memory',
+		detail: 'Summarizes older messages',
+		tone: 'amber',
+		icon: 'SM',
+		width: 180,
+		minHeight: 60,
+		inputs: [],
+		outputs: [{ id: 'memory', label: '', type: 'memory' }]
 	}
 ];
 
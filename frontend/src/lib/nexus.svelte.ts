@@ -1,6 +1,6 @@
 import { browser } from '$app/environment';
 import type { Node, Edge } from '@xyflow/svelte';
-import type { Credential, McpServer, ExecutionResult, ActiveView, SidebarTab, ViewMode } from './types';
+import type { Credential, McpServer, ExecutionResult, ActiveView, SidebarTab, ViewMode, KnowledgeBase, KnowledgeDocument, KnowledgeChunkMatch } from './types';
 import type { NodeKind } from '$lib/flow/nodes/definitions';
 
 const API_BASE = import.meta.env.VITE_API_BASE ?? 'http://localhost:3001';
@@ -10,6 +10,13 @@ export class NexusState {
 	activeView = $state<ActiveView>('canvas');
 	activeTab = $state<SidebarTab>('nodes');
 	workflowName = $state('Untitled Workflow');
+	/** Server-side id of the workflow being edited, created the first time it is activated. */
+	workflowId = $state<string | null>(null);
+	workflowActive = $state(false);
+	/** Stored with the workflow on the server, e.g. `errorWorkflowId`. */
+	workflowSettings = $state<Record<string, any>>({});
+	isTogglingActive = $state(false);
+	activationErrors = $state<string[]>([]);
 	leftSidebarOpen = $state(true);
 	rightSidebarOpen = $state(true);
 	isMaximized = $state(false);
@@ -37,6 +44,10 @@ export class NexusState {
 	selectedDataTable = $state<any | null>(null);
 	selectedDataTableRows = $state<any[]>([]);
 	isFetchingDataTableRows = $state(false);
+	knowledgeBases = $state<KnowledgeBase[]>([]);
+	isFetchingKnowledgeBases = $state(false);
+	selectedKnowledgeBase = $state<KnowledgeBase | null>(null);
+	knowledgeDocuments = $state<KnowledgeDocument[]>([]);
 
 	// Execution State
 	isExecuting = $state(false);
@@ -55,6 +66,7 @@ export class NexusState {
 	constructor() {
 		if (browser) {
 			this.loadFromStorage();
+			this.fetchWorkflowStatus();
 			this.fetchCredentials();
 			this.fetchMcpServers();
 			
@@ -140,12 +152,15 @@ export class NexusState {
 		const savedEdges = localStorage.getItem('nexus_edges');
 		const savedName = localStorage.getItem('nexus_name');
 		const savedMcp = localStorage.getItem('nexus_mcp_servers');
+		this.workflowId = localStorage.getItem('nexus_workflow_id');
+		const savedSettings = localStorage.getItem('nexus_workflow_settings');
 
 		try {
 			if (savedNodes) this.nodes = JSON.parse(savedNodes);
 			if (savedMcp) this.mcpServers = JSON.parse(savedMcp);
 			if (savedEdges) this.edges = JSON.parse(savedEdges);
 			if (savedName) this.workflowName = savedName;
+			if (savedSettings) this.workflowSettings = JSON.parse(savedSettings);
 		} catch (e) {
 			console.error('Failed to load storage:', e);
 		}
@@ -166,7 +181,10 @@ export class NexusState {
 			localStorage.setItem('nexus_nodes', JSON.stringify(this.nodes));
 			localStorage.setItem('nexus_edges', JSON.stringify(this.edges));
 			localStorage.setItem('nexus_name', this.workflowName);
+			localStorage.setItem('nexus_workflow_settings', JSON.stringify(this.workflowSettings));
 			localStorage.setItem('nexus_mcp_servers', JSON.stringify(this.mcpServers));
+			if (this.workflowId) localStorage.setItem('nexus_workflow_id', this.workflowId);
+			else localStorage.removeItem('nexus_workflow_id');
 		} catch (e) {
 			console.error('Save failed:', e);
 		}
@@ -175,11 +193,109 @@ export class NexusState {
 	async saveWorkflow() {
 		this.isSaving = true;
 		this.saveToStorage();
+		// Active workflows also update their live triggers on the server, and settings only apply there
+		if (this.workflowId || Object.keys(this.workflowSettings).length) {
+			const errors = await this.pushWorkflow();
+			this.activationErrors = errors ?? [];
+		}
 		// Artificial delay for UI feedback
 		await new Promise(resolve => setTimeout(resolve, 500));
 		this.isSaving = false;
 	}
 
+	/** Node and edge ids in the editor aren't always UUIDs; the server needs them to be. */
+	private workflowPayload() {
+		const idMap = new Map<string, string>();
+		const normalizeId = (oldId: string) => {
+			if (this.isValidUuid(oldId)) return oldId;
+			if (!idMap.has(oldId)) idMap.set(oldId, crypto.randomUUID());
+			return idMap.get(oldId)!;
+		};
+		return {
+			name: this.workflowName,
+			description: '',
+			settings: this.workflowSettings,
+			nodes: this.nodes.map(n => ({
+				id: normalizeId(n.id),
+				kind: n.data.kind,
+				label: n.data.label,
+				position: n.position,
+				config: n.data.config || {}
+			})),
+			edges: this.edges.map(e => ({
+				id: normalizeId(e.id),
+				from: normalizeId(e.source),
+				to: normalizeId(e.target),
+				fromPort: e.sourceHandle ?? undefined,
+				toPort: e.targetHandle ?? undefined
+			}))
+		};
+	}
+
+	private async responseErrors(response: Response): Promise<string[]> {
+		const body = await response.json().catch(() => null);
+		return body?.errors ?? [body?.error ?? `Request failed (${response.status})`];
+	}
+
+	/** Creates or updates the server copy of the workflow. Returns the errors, or null on success. */
+	private async pushWorkflow(): Promise<string[] | null> {
+		try {
+			const body = JSON.stringify(this.workflowPayload());
+			let response = this.workflowId
+				? await fetch(`${API_BASE}/api/workflows/${this.workflowId}`, { method: 'PUT', headers: { 'Content-Type': 'application/json' }, body })
+				: null;
+			if (!response || response.status === 404) {
+				response = await fetch(`${API_BASE}/api/workflows`, { method: 'POST', headers: { 'Content-Type': 'application/json' }, body });
+			}
+			if (!response.ok) {
+				const errors = await this.responseErrors(response);
+				// A conflicting save deactivates the workflow on the server
+				if (response.status === 409) this.workflowActive = false;
+				return errors;
+			}
+			const workflow = await response.json();
+			this.workflowId = workflow.id;
+			this.workflowActive = workflow.active;
+			this.saveToStorage();
+			return null;
+		} catch (e) {
+			return [(e as Error).message];
+		}
+	}
+
+	async fetchWorkflowStatus() {
+		if (!this.workflowId) return;
+		try {
+			const response = await fetch(`${API_BASE}/api/workflows/${this.workflowId}`);
+			if (response.ok) this.workflowActive = (await response.json()).active;
+		} catch (e) {
+			console.error('Failed to fetch workflow status:', e);
+		}
+	}
+
+	/** Turns the workflow's triggers (webhooks, schedules, Slack, forms...) on or off. */
+	async setWorkflowActive(active: boolean) {
+		this.isTogglingActive = true;
+		this.activationErrors = [];
+		try {
+			const errors = await this.pushWorkflow();
+			if (errors) {
+				this.activationErrors = errors;
+				return;
+			}
+			const response = await fetch(`${API_BASE}/api/workflows/${this.workflowId}/${active ? 'activate' : 'deactivate'}`, { method: 'POST' });
+			if (!response.ok) {
+				this.activationErrors = await this.responseErrors(response);
+				return;
+			}
+			this.workflowActive = (await response.json()).active;
+		} catch (e) {
+			this.activationErrors = [(e as Error).message];
+		} finally {
+			this.isTogglingActive = false;
+		}
+	}
+
 	// Actions
 	async fetchCredentials() {
 		this.isFetchingCredentials = true;
@@ -202,7 +318,26 @@ export class NexusState {
 		return /^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$/i.test(id);
 	}
 
-	async triggerWorkflowWithInput(initialInput: any) {
+	/** Reserves an execution id on the server, so its events can be watched before it is started. */
+	async reserveExecution(): Promise<string> {
+		const response = await fetch(`${API_BASE}/api/executions`, { method: 'POST' });
+		if (!response.ok) throw new Error(`Failed to reserve execution (${response.status})`);
+		return (await response.json()).id;
+	}
+
+	/** Opens the live event stream of an execution that will be started with a reserved `executionId`. */
+	watchExecution(executionId: string, onEvent: (event: any) => void): Promise<EventSource> {
+		const source = new EventSource(`${API_BASE}/api/executions/${executionId}/events`);
+		for (const type of ['node_started', 'token', 'tool_call_delta', 'node_finished', 'finished']) {
+			source.addEventListener(type, (e) => onEvent(JSON.parse((e as MessageEvent).data)));
+		}
+		return new Promise((resolve) => {
+			source.onopen = () => resolve(source);
+			source.onerror = () => resolve(source);
+		});
+	}
+
+	async triggerWorkflowWithInput(initialInput: any, executionId?: string) {
 		console.log('Triggering workflow with input:', initialInput);
 		this.isExecuting = true;
 		this.executionResults = { nodeResults: {}, summary: null };
@@ -240,7 +375,9 @@ export class NexusState {
 			const payloadEdges = this.edges.map(e => ({
 				id: normalizeId(e.id),
 				from: normalizeId(e.source),
-				to: normalizeId(e.target)
+				to: normalizeId(e.target),
+				fromPort: e.sourceHandle ?? undefined,
+				toPort: e.targetHandle ?? undefined
 			}));
 
 			const triggerNodeId = chatTriggerNode ? normalizeId(chatTriggerNode.id) : null;
@@ -252,7 +389,8 @@ export class NexusState {
 					workflow_id: 'current',
 					nodes: payloadNodes,
 					edges: payloadEdges,
-					trigger_node_id: triggerNodeId
+					trigger_node_id: triggerNodeId,
+					execution_id: executionId
 				})
 			});
 
@@ -521,6 +659,108 @@ export class NexusState {
 		}
 	}
 
+	async fetchKnowledgeBases() {
+		this.isFetchingKnowledgeBases = true;
+		try {
+			const response = await fetch(`${API_BASE}/api/knowledge-bases`);
+			if (response.ok) {
+				this.knowledgeBases = await response.json();
+			}
+		} catch (e) {
+			console.error('Fetch knowledge bases failed:', e);
+		} finally {
+			this.isFetchingKnowledgeBases = false;
+		}
+	}
+
+	async saveKnowledgeBase(input: Partial<KnowledgeBase>, id?: string): Promise<string | null> {
+		try {
+			const response = await fetch(`${API_BASE}/api/knowledge-bases${id ? `/${id}` : ''}`, {
+				method: id ? 'PUT' : 'POST',
+				headers: { 'Content-Type': 'application/json' },
+				body: JSON.stringify(input)
+			});
+			if (!response.ok) {
+				const body = await response.json().catch(() => null);
+				return body?.error ?? `Request failed with status ${response.status}`;
+			}
+			const saved = await response.json();
+			if (this.selectedKnowledgeBase?.id === saved.id) {
+				this.selectedKnowledgeBase = saved;
+			}
+			await this.fetchKnowledgeBases();
+			return null;
+		} catch (e) {
+			console.error('Save knowledge base failed:', e);
+			return (e as Error).message;
+		}
+	}
+
+	async deleteKnowledgeBase(id: string) {
+		try {
+			const response = await fetch(`${API_BASE}/api/knowledge-bases/${id}`, { method: 'DELETE' });
+			if (response.ok) {
+				await this.fetchKnowledgeBases();
+				if (this.selectedKnowledgeBase?.id === id) {
+					this.selectedKnowledgeBase = null;
+					this.knowledgeDocuments = [];
+				}
+			}
+		} catch (e) {
+			console.error('Delete knowledge base failed:', e);
+		}
+	}
+
+	async fetchKnowledgeDocuments(kbId: string) {
+		try {
+			const response = await fetch(`${API_BASE}/api/knowledge-bases/${kbId}/documents`);
+			if (response.ok) {
+				this.knowledgeDocuments = await response.json();
+			}
+		} catch (e) {
+			console.error('Fetch knowledge documents failed:', e);
+		}
+	}
+
+	async uploadKnowledgeDocuments(kbId: string, files: FileList | File[]): Promise<string | null> {
+		const form = new FormData();
+		for (const file of files) form.append('files', file);
+		try {
+			const response = await fetch(`${API_BASE}/api/knowledge-bases/${kbId}/documents`, { method: 'POST', body: form });
+			if (!response.ok) {
+				const body = await response.json().catch(() => null);
+				return body?.error ?? `Upload failed with status ${response.status}`;
+			}
+			await this.fetchKnowledgeDocuments(kbId);
+			return null;
+		} catch (e) {
+			console.error('Upload knowledge documents failed:', e);
+			return (e as Error).message;
+		}
+	}
+
+	async deleteKnowledgeDocument(kbId: string, documentId: string) {
+		try {
+			const response = await fetch(`${API_BASE}/api/knowledge-bases/${kbId}/documents/${documentId}`, { method: 'DELETE' });
+			if (response.ok) {
+				await this.fetchKnowledgeDocuments(kbId);
+			}
+		} catch (e) {
+			console.error('Delete knowledge document failed:', e);
+		}
+	}
+
+	async searchKnowledgeBase(kbId: string, query: string, topK: number): Promise<KnowledgeChunkMatch[]> {
+		const response = await fetch(`${API_BASE}/api/knowledge-bases/${kbId}/search`, {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({ query, top_k: topK })
+		});
+		const body = await response.json().catch(() => null);
+		if (!response.ok) throw new Error(body?.error ?? `Search failed with status ${response.status}`);
+		return body;
+	}
+
 	selectExecution(execution: any) {
 		this.selectedExecution = execution;
 		this.nodeViewModes = {};
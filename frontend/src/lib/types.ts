@@ -18,6 +18,40 @@ export interface McpServer {
 	status: 'connected' | 'error' | 'none' | 'disconnected';
 }
 
+export interface KnowledgeBase {
+	id: string;
+	name: string;
+	description: string | null;
+	embedding_provider: string;
+	embedding_model: string;
+	dimensions: number | null;
+	chunk_size: number;
+	chunk_overlap: number;
+	credential_id: string | null;
+	created_at: string;
+	updated_at: string;
+}
+
+export interface KnowledgeDocument {
+	id: string;
+	knowledge_base_id: string;
+	file_name: string;
+	mime_type: string;
+	size: number;
+	status: 'processing' | 'ready' | 'failed';
+	error: string | null;
+	chunk_count: number;
+	created_at: string;
+}
+
+export interface KnowledgeChunkMatch {
+	content: string;
+	score: number;
+	documentId: string;
+	documentName: string;
+	chunkIndex: number;
+}
+
 export interface ExecutionResult {
 	nodeResults: Record<
 		string,
@@ -0,0 +1,264 @@
+use async_trait::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
+use super::llm::{check_status, read_sse, ChatDelta, ChatMessage, ChatRequest, ChatResponse, ContentPart, LlmProvider, ProviderSettings, TokenUsage, ToolCall};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const API_VERSION: &str = "2023-06-01";
+/// The Messages API requires `max_tokens`; used when the node does not set one.
+pub const DEFAULT_MAX_TOKENS: i32 = 4096;
+
+/// Client for the Anthropic Messages API.
+#[derive(Clone)]
+pub struct AnthropicClient {
+    api_key: String,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl AnthropicClient {
+    pub fn new(settings: ProviderSettings) -> Self {
+        Self {
+            api_key: settings.api_key.unwrap_or_default(),
+            base_url: settings.base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()).trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn send(&self, request: &ChatRequest, stream: bool) -> Result<reqwest::Response, String> {
+        let response = self.client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&request_body(request, stream))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        check_status("anthropic", response).await
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicClient {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse, String> {
+        let raw: serde_json::Value = self.send(&request, false).await?.json().await.map_err(|e| e.to_string())?;
+
+        let mut message = ChatMessage::assistant("");
+        for block in raw.get("content").and_then(|v| v.as_array()).ok_or("Invalid LLM response")? {
+            match block.get("type").and_then(|v| v.as_str()) {
+                Some("text") => message.content.push_str(block.get("text").and_then(|v| v.as_str()).unwrap_or("")),
+                Some("tool_use") => message.tool_calls.push(ToolCall {
+                    id: block.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                    name: block.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                    arguments: block.get("input").map(|v| v.to_string()).unwrap_or_else(|| "{}".to_string()),
+                }),
+                _ => {}
+            }
+        }
+        let usage = raw.get("usage").map(|u| TokenUsage {
+            input_tokens: u.get("input_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+            output_tokens: u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+        });
+        Ok(ChatResponse {
+            message,
+            finish_reason: raw.get("stop_reason").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            usage,
+            raw,
+        })
+    }
+
+    async fn chat_stream(&self, request: ChatRequest, deltas: UnboundedSender<ChatDelta>) -> Result<ChatResponse, String> {
+        let response = self.send(&request, true).await?;
+
+        let mut message = ChatMessage::assistant("");
+        let mut finish_reason = None;
+        let mut usage = TokenUsage::default();
+        let mut id = serde_json::Value::Null;
+        // Content block index -> position in `message.tool_calls`.
+        let mut tool_blocks: Vec<(u64, usize)> = Vec::new();
+        read_sse(response, |data| {
+            let event: serde_json::Value = serde_json::from_str(data).map_err(|e| format!("Invalid stream event: {}", e))?;
+            let block_index = event.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+            match event.get("type").and_then(|v| v.as_str()) {
+                Some("message_start") => {
+                    let start = event.get("message").cloned().unwrap_or_default();
+                    id = start.get("id").cloned().unwrap_or_default();
+                    usage.input_tokens = start.pointer("/usage/input_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+                }
+                Some("content_block_start") => {
+                    let block = event.get("content_block").cloned().unwrap_or_default();
+                    if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
+                        let call = ToolCall {
+                            id: block.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                            name: block.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+                            arguments: String::new(),
+                        };
+                        let index = message.tool_calls.len();
+                        let _ = deltas.send(ChatDelta::ToolCall { index, id: Some(call.id.clone()), name: Some(call.name.clone()), arguments: String::new() });
+                        tool_blocks.push((block_index, index));
+                        message.tool_calls.push(call);
+                    }
+                }
+                Some("content_block_delta") => {
+                    let delta = event.get("delta").cloned().unwrap_or_default();
+                    match delta.get("type").and_then(|v| v.as_str()) {
+                        Some("text_delta") => {
+                            let text = delta.get("text").and_then(|v| v.as_str()).unwrap_or("");
+                            message.content.push_str(text);
+                            let _ = deltas.send(ChatDelta::Text(text.to_string()));
+                        }
+                        Some("input_json_delta") => {
+                            let partial = delta.get("partial_json").and_then(|v| v.as_str()).unwrap_or("");
+                            if let Some(&(_, index)) = tool_blocks.iter().find(|(b, _)| *b == block_index) {
+                                message.tool_calls[index].arguments.push_str(partial);
+                                let _ = deltas.send(ChatDelta::ToolCall { index, id: None, name: None, arguments: partial.to_string() });
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Some("message_delta") => {
+                    if let Some(reason) = event.pointer("/delta/stop_reason").and_then(|v| v.as_str()) {
+                        finish_reason = Some(reason.to_string());
+                    }
+                    if let Some(output) = event.pointer("/usage/output_tokens").and_then(|v| v.as_u64()) {
+                        usage.output_tokens = output;
+                    }
+                }
+                Some("message_stop") => return Ok(false),
+                Some("error") => {
+                    let error = event.pointer("/error/message").and_then(|v| v.as_str()).unwrap_or("unknown error");
+                    return Err(format!("anthropic API error: {}", error));
+                }
+                _ => {}
+            }
+            Ok(true)
+        }).await?;
+
+        // Tool calls with no input stream an empty string rather than `{}`.
+        for call in &mut message.tool_calls {
+            if call.arguments.trim().is_empty() {
+                call.arguments = "{}".to_string();
+            }
+        }
+        let raw = serde_json::json!({
+            "id": id,
+            "type": "message",
+            "role": "assistant",
+            "model": request.model,
+            "content": assistant_blocks(&message),
+            "stop_reason": finish_reason,
+            "usage": usage,
+        });
+        Ok(ChatResponse { message, finish_reason, usage: Some(usage), raw })
+    }
+}
+
+/// Builds a Messages API request. System messages become the top-level `system` prompt, tool results
+/// become `tool_result` blocks in a user turn, empty assistant turns are dropped and consecutive turns
+/// of the same role are merged.
+fn request_body(request: &ChatRequest, stream: bool) -> serde_json::Value {
+    let mut system: Vec<String> = request.messages.iter().filter(|m| m.role == "system").map(|m| m.content.clone()).collect();
+    // The Messages API has no `response_format`; ask for the schema in the system prompt instead.
+    if let Some(schema) = request.response_format.as_ref().and_then(|f| f.pointer("/json_schema/schema")) {
+        system.push(format!("Reply with only a JSON value, without any other text, that matches this JSON Schema:\n{}", schema));
+    }
+
+    let mut messages: Vec<serde_json::Value> = Vec::new();
+    for message in request.messages.iter().filter(|m| m.role != "system") {
+        let (role, blocks) = match message.role.as_str() {
+            "assistant" => ("assistant", assistant_blocks(message)),
+            "tool" => ("user", vec![serde_json::json!({
+                "type": "tool_result",
+                "tool_use_id": message.tool_call_id.clone().unwrap_or_default(),
+                "content": message.content,
+            })]),
+            _ => ("user", user_blocks(message)),
+        };
+        // The API rejects empty assistant turns, e.g. a reply that was cut off before any text
+        if blocks.is_empty() {
+            continue;
+        }
+        match messages.last_mut() {
+            Some(last) if last["role"] == role => {
+                if let Some(content) = last["content"].as_array_mut() {
+                    content.extend(blocks);
+                }
+            }
+            _ => messages.push(serde_json::json!({ "role": role, "content": blocks })),
+        }
+    }
+
+    let mut body = serde_json::json!({
+        "model": request.model,
+        "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "messages": messages,
+    });
+    if !system.is_empty() {
+        body["system"] = serde_json::json!(system.join("\n\n"));
+    }
+    if let Some(temperature) = request.temperature {
+        body["temperature"] = serde_json::json!(temperature);
+    }
+    if let Some(top_p) = request.top_p {
+        body["top_p"] = serde_json::json!(top_p);
+    }
+    if !request.tools.is_empty() {
+        body["tools"] = request.tools.iter().map(|t| serde_json::json!({
+            "name": t.name,
+            "description": t.description,
+            "input_schema": t.parameters,
+        })).collect();
+    }
+    if let Some(choice) = &request.tool_choice {
+        // Accept the OpenAI spellings so nodes can set `tool_choice` the same way for every provider.
+        body["tool_choice"] = match choice.as_str() {
+            Some("auto") => serde_json::json!({ "type": "auto" }),
+            Some("required") => serde_json::json!({ "type": "any" }),
+            Some("none") => serde_json::json!({ "type": "none" }),
+            _ => choice.clone(),
+        };
+    }
+    if stream {
+        body["stream"] = serde_json::json!(true);
+    }
+    body
+}
+
+fn user_blocks(message: &ChatMessage) -> Vec<serde_json::Value> {
+    let mut blocks = Vec::new();
+    if !message.content.is_empty() || message.parts.is_empty() {
+        blocks.push(serde_json::json!({ "type": "text", "text": message.content }));
+    }
+    // Anthropic has no equivalent of OpenAI's image `detail`
+    for part in &message.parts {
+        blocks.push(match part {
+            ContentPart::Text { text } => serde_json::json!({ "type": "text", "text": text }),
+            ContentPart::ImageUrl { url, .. } => serde_json::json!({ "type": "image", "source": { "type": "url", "url": url } }),
+            ContentPart::ImageBase64 { media_type, data, .. } => serde_json::json!({
+                "type": "image",
+                "source": { "type": "base64", "media_type": media_type, "data": data },
+            }),
+        });
+    }
+    blocks
+}
+
+fn assistant_blocks(message: &ChatMessage) -> Vec<serde_json::Value> {
+    let mut blocks = Vec::new();
+    if !message.content.is_empty() {
+        blocks.push(serde_json::json!({ "type": "text", "text": message.content }));
+    }
+    for call in &message.tool_calls {
+        let input: serde_json::Value = serde_json::from_str(&call.arguments).unwrap_or_else(|_| serde_json::json!({}));
+        blocks.push(serde_json::json!({ "type": "tool_use", "id": call.id, "name": call.name, "input": input }));
+    }
+    blocks
+}
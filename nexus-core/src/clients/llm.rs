@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// A message in a provider-neutral chat conversation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Further text and images sent after `content`, for vision models.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<ContentPart>,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: "assistant".to_string(), content: content.into(), ..Default::default() }
+    }
+
+    /// The result of the tool call `call_id`, sent back to the model.
+    pub fn tool(call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self { role: "tool".to_string(), content: content.into(), tool_call_id: Some(call_id.into()), ..Default::default() }
+    }
+
+    pub fn with_parts(mut self, parts: Vec<ContentPart>) -> Self {
+        self.parts = parts;
+        self
+    }
+}
+
+/// A piece of multimodal message content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    /// An image the provider downloads itself. `detail` is OpenAI's `low`, `high` or `auto`.
+    ImageUrl { url: String, #[serde(default, skip_serializing_if = "Option::is_none")] detail: Option<String> },
+    /// An image sent inline as base64.
+    ImageBase64 { media_type: String, data: String, #[serde(default, skip_serializing_if = "Option::is_none")] detail: Option<String> },
+}
+
+/// A function call requested by the model. `arguments` is the raw JSON text the model produced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+}
+
+/// A function the model may call, with its parameters as a JSON Schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<i32>,
+    pub top_p: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    pub response_format: Option<serde_json::Value>,
+    pub tools: Vec<ToolDefinition>,
+    pub tool_choice: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// Result of [`LlmProvider::embeddings`].
+#[derive(Debug, Clone, Default)]
+pub struct Embeddings {
+    pub vectors: Vec<Vec<f32>>,
+    pub usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub message: ChatMessage,
+    pub finish_reason: Option<String>,
+    pub usage: Option<TokenUsage>,
+    /// The provider's response body, as returned by the LLM nodes.
+    pub raw: serde_json::Value,
+}
+
+/// An incremental piece of a streamed response.
+#[derive(Debug, Clone)]
+pub enum ChatDelta {
+    Text(String),
+    /// A fragment of the tool call at `index`. `id` and `name` arrive once, `arguments` in pieces.
+    ToolCall { index: usize, id: Option<String>, name: Option<String>, arguments: String },
+}
+
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Registry name of the provider, used in error messages.
+    fn name(&self) -> &str;
+
+    /// Endpoint requests are sent to.
+    fn base_url(&self) -> &str;
+
+    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse, String>;
+
+    /// Like `chat`, but sends deltas to `deltas` while the response is generated.
+    /// Providers without streaming send the whole text as a single delta.
+    async fn chat_stream(&self, request: ChatRequest, deltas: UnboundedSender<ChatDelta>) -> Result<ChatResponse, String> {
+        let response = self.chat(request).await?;
+        if !response.message.content.is_empty() {
+            let _ = deltas.send(ChatDelta::Text(response.message.content.clone()));
+        }
+        Ok(response)
+    }
+
+    /// Embeds each input, returning one vector per input in the same order.
+    async fn embeddings(&self, _model: &str, _inputs: Vec<String>, _dimensions: Option<u32>) -> Result<Embeddings, String> {
+        Err(format!("Provider '{}' does not support embeddings", self.name()))
+    }
+}
+
+/// Connection settings for a provider, usually read from a credential.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderSettings {
+    pub api_key: Option<String>,
+    pub base_url: Option<String>,
+}
+
+impl ProviderSettings {
+    /// Reads `api_key` and `base_url` from a credential's data.
+    pub fn from_credential(data: &serde_json::Value) -> Self {
+        let field = |key: &str| data.get(key).and_then(|v| v.as_str()).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+        Self { api_key: field("api_key"), base_url: field("base_url") }
+    }
+}
+
+type ProviderFactory = fn(ProviderSettings) -> Arc<dyn LlmProvider>;
+
+struct ProviderEntry {
+    api_key_env: &'static str,
+    base_url_env: &'static str,
+    requires_api_key: bool,
+    requires_base_url: bool,
+    build: ProviderFactory,
+}
+
+/// Maps provider names used in node configs and credentials to their clients.
+pub struct ProviderRegistry {
+    providers: HashMap<&'static str, ProviderEntry>,
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        let mut registry = Self { providers: HashMap::new() };
+        registry.register("openai", "OPENAI_API_KEY", "OPENAI_BASE_URL", true, false, |s| Arc::new(super::OpenAiClient::new(s)));
+        registry.register("openrouter", "OPENROUTER_API_KEY", "OPENROUTER_BASE_URL", true, false, |s| Arc::new(super::openrouter::client(s)));
+        registry.register("anthropic", "ANTHROPIC_API_KEY", "ANTHROPIC_BASE_URL", true, false, |s| Arc::new(super::anthropic::AnthropicClient::new(s)));
+        // vLLM, Ollama, LM Studio and other servers speaking the OpenAI chat completions API.
+        registry.register("openai-compatible", "OPENAI_COMPATIBLE_API_KEY", "OPENAI_COMPATIBLE_BASE_URL", false, true, |s| Arc::new(super::OpenAiClient::new(s).named("openai-compatible")));
+        registry
+    }
+}
+
+impl ProviderRegistry {
+    /// Adds a provider. Settings missing from the credential fall back to the given environment variables.
+    pub fn register(&mut self, name: &'static str, api_key_env: &'static str, base_url_env: &'static str, requires_api_key: bool, requires_base_url: bool, build: ProviderFactory) {
+        self.providers.insert(name, ProviderEntry { api_key_env, base_url_env, requires_api_key, requires_base_url, build });
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.providers.contains_key(name)
+    }
+
+    /// Completes `settings` from the environment and checks that everything the provider needs is set.
+    pub fn settings(&self, name: &str, mut settings: ProviderSettings) -> Result<ProviderSettings, String> {
+        let entry = self.providers.get(name).ok_or_else(|| format!("Unknown LLM provider: {}", name))?;
+        let env = |key: &str| std::env::var(key).ok().filter(|s| !s.trim().is_empty());
+        if settings.api_key.is_none() {
+            settings.api_key = env(entry.api_key_env);
+        }
+        if settings.base_url.is_none() {
+            settings.base_url = env(entry.base_url_env);
+        }
+        if entry.requires_api_key && settings.api_key.is_none() {
+            return Err(format!("{} not set", entry.api_key_env));
+        }
+        if entry.requires_base_url && settings.base_url.is_none() {
+            return Err(format!("{} not set", entry.base_url_env));
+        }
+        Ok(settings)
+    }
+
+    pub fn create(&self, name: &str, settings: ProviderSettings) -> Result<Arc<dyn LlmProvider>, String> {
+        let settings = self.settings(name, settings)?;
+        Ok((self.providers[name].build)(settings))
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: ProviderRegistry = ProviderRegistry::default();
+}
+
+pub fn registry() -> &'static ProviderRegistry {
+    &REGISTRY
+}
+
+/// Reads a `text/event-stream` response and calls `on_data` with the payload of each `data:` line.
+/// Stops at the end of the body or when `on_data` returns `false`.
+pub(crate) async fn read_sse(response: reqwest::Response, mut on_data: impl FnMut(&str) -> Result<bool, String>) -> Result<(), String> {
+    use futures_util::StreamExt;
+
+    let mut stream = response.bytes_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        buffer.extend_from_slice(&chunk.map_err(|e| format!("Stream error: {}", e))?);
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:")
+                && !on_data(data.trim_start())?
+            {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Turns a non-success HTTP response into an error that includes the provider's message.
+pub(crate) async fn check_status(provider: &str, response: reqwest::Response) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body).ok()
+        .and_then(|v| v.get("error").and_then(|e| e.get("message").or(Some(e))).and_then(|m| m.as_str()).map(|s| s.to_string()))
+        .unwrap_or(body);
+    Err(format!("{} API error ({}): {}", provider, status.as_u16(), message))
+}
@@ -1,5 +1,7 @@
+pub mod anthropic;
+pub mod llm;
 pub mod openai;
 pub mod openrouter;
 
-pub use openai::OpenAiClient;
-pub use openrouter::OpenRouterClient;
+pub use llm::{registry, ChatMessage, ChatRequest, ContentPart, ChatResponse, LlmProvider, ProviderSettings, ToolDefinition};
+pub use openai::{FileUpload, ImageOptions, OpenAiClient, TranscriptionOptions};
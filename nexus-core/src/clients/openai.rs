@@ -1,108 +1,404 @@
-use serde::{Deserialize, Serialize};
+use async_trait::async_trait;
+use tokio::sync::mpsc::UnboundedSender;
+use super::llm::{check_status, read_sse, ChatDelta, ChatMessage, ChatRequest, ChatResponse, ContentPart, Embeddings, LlmProvider, ProviderSettings, TokenUsage, ToolCall};
 
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+/// Inputs per embeddings request. OpenAI accepts up to 2048, but large batches hit the per-request token limit.
+const EMBEDDINGS_BATCH_SIZE: usize = 256;
+
+/// Client for the OpenAI API and any server that implements its chat completions endpoint.
 #[derive(Clone)]
 pub struct OpenAiClient {
-    api_key: String,
+    name: &'static str,
+    api_key: Option<String>,
+    base_url: String,
+    headers: Vec<(&'static str, String)>,
     client: reqwest::Client,
 }
 
 impl OpenAiClient {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(settings: ProviderSettings) -> Self {
         Self {
-            api_key,
+            name: "openai",
+            api_key: settings.api_key,
+            base_url: settings.base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()).trim_end_matches('/').to_string(),
+            headers: Vec::new(),
             client: reqwest::Client::new(),
         }
     }
 
-    pub async fn generate(
-        &self,
-        model: &str,
-        messages: Vec<OpenAiMessage>,
-        temperature: Option<f32>,
-        max_tokens: Option<i32>,
-        tools: Option<serde_json::Value>,
-    ) -> Result<serde_json::Value, reqwest::Error> {
-        let request = OpenAiRequest {
-            model: model.to_string(),
-            messages,
-            temperature,
-            max_tokens,
-            tools,
-            tool_choice: None,
-        };
-
-        let response = self
-            .client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
-
-        let result: serde_json::Value = response.json().await?;
-        Ok(result)
-    }
-
-    pub async fn images_generate(
-        &self,
-        prompt: &str,
-        model: &str,
-        size: &str,
-        _quality: &str,
-        n: i32,
-    ) -> Result<serde_json::Value, reqwest::Error> {
-        let request = serde_json::json!({
-            "model": model,
-            "prompt": prompt,
-            "size": size,
-            "n": n,
+    /// Sets the provider name reported in errors.
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Adds a header sent with every request.
+    pub fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let mut builder = self.client.post(format!("{}{}", self.base_url, path));
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(*name, value);
+        }
+        builder
+    }
+
+    fn chat_body(request: &ChatRequest, stream: bool) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "model": request.model,
+            "messages": request.messages.iter().map(message_to_wire).collect::<Vec<_>>(),
         });
+        let fields = [
+            ("temperature", request.temperature.map(|v| serde_json::json!(v))),
+            ("max_tokens", request.max_tokens.map(|v| serde_json::json!(v))),
+            ("top_p", request.top_p.map(|v| serde_json::json!(v))),
+            ("frequency_penalty", request.frequency_penalty.map(|v| serde_json::json!(v))),
+            ("presence_penalty", request.presence_penalty.map(|v| serde_json::json!(v))),
+            ("response_format", request.response_format.clone()),
+            ("tool_choice", request.tool_choice.clone()),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                body[key] = value;
+            }
+        }
+        if !request.tools.is_empty() {
+            body["tools"] = request.tools.iter().map(|t| serde_json::json!({
+                "type": "function",
+                "function": { "name": t.name, "description": t.description, "parameters": t.parameters }
+            })).collect();
+        }
+        if stream {
+            body["stream"] = serde_json::json!(true);
+            body["stream_options"] = serde_json::json!({ "include_usage": true });
+        }
+        body
+    }
+
+    pub async fn images_generate(&self, prompt: &str, options: &ImageOptions) -> Result<serde_json::Value, String> {
+        let mut request = serde_json::json!({ "model": options.model, "prompt": prompt });
+        let fields = [
+            ("size", &options.size),
+            ("quality", &options.quality),
+            ("style", &options.style),
+            ("response_format", &options.response_format),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                request[key] = serde_json::json!(value);
+            }
+        }
+        if let Some(n) = options.n {
+            request["n"] = serde_json::json!(n);
+        }
+
+        let response = self.post("/images/generations").json(&request).send().await.map_err(|e| e.to_string())?;
+        check_status(self.name, response).await?.json().await.map_err(|e| e.to_string())
+    }
+
+    /// Edits `image` following `prompt`. Transparent areas of `mask`, if given, mark what may change.
+    pub async fn images_edit(&self, image: FileUpload, mask: Option<FileUpload>, prompt: &str, options: &ImageOptions) -> Result<serde_json::Value, String> {
+        let mut form = options.form().text("prompt", prompt.to_string()).part("image", image.part()?);
+        if let Some(mask) = mask {
+            form = form.part("mask", mask.part()?);
+        }
+        let response = self.post("/images/edits").multipart(form).send().await.map_err(|e| e.to_string())?;
+        check_status(self.name, response).await?.json().await.map_err(|e| e.to_string())
+    }
+
+    pub async fn images_variation(&self, image: FileUpload, options: &ImageOptions) -> Result<serde_json::Value, String> {
+        let form = options.form().part("image", image.part()?);
+        let response = self.post("/images/variations").multipart(form).send().await.map_err(|e| e.to_string())?;
+        check_status(self.name, response).await?.json().await.map_err(|e| e.to_string())
+    }
+
+    /// Transcribes `file`, or translates it to English when `translate` is set. Text formats
+    /// (`text`, `srt`, `vtt`) come back as `{ "text": ... }`.
+    pub async fn audio_transcription(&self, file: FileUpload, translate: bool, options: &TranscriptionOptions) -> Result<serde_json::Value, String> {
+        let mut form = reqwest::multipart::Form::new().text("model", options.model.clone()).part("file", file.part()?);
+        let fields = [
+            ("language", options.language.clone().filter(|_| !translate)),
+            ("prompt", options.prompt.clone()),
+            ("response_format", options.response_format.clone()),
+            ("temperature", options.temperature.map(|t| t.to_string())),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                form = form.text(key, value);
+            }
+        }
+        let path = if translate { "/audio/translations" } else { "/audio/transcriptions" };
+        let response = self.post(path).multipart(form).send().await.map_err(|e| e.to_string())?;
+        let body = check_status(self.name, response).await?.text().await.map_err(|e| e.to_string())?;
+        match options.response_format.as_deref() {
+            None | Some("json") | Some("verbose_json") => serde_json::from_str(&body).map_err(|e| e.to_string()),
+            _ => Ok(serde_json::json!({ "text": body })),
+        }
+    }
+
+    /// Reads `input` aloud. Returns the audio and its MIME type.
+    pub async fn audio_speech(&self, model: &str, input: &str, voice: &str, response_format: Option<&str>, speed: Option<f64>) -> Result<(Vec<u8>, String), String> {
+        let mut request = serde_json::json!({ "model": model, "input": input, "voice": voice });
+        if let Some(format) = response_format {
+            request["response_format"] = serde_json::json!(format);
+        }
+        if let Some(speed) = speed {
+            request["speed"] = serde_json::json!(speed);
+        }
+        let response = self.post("/audio/speech").json(&request).send().await.map_err(|e| e.to_string())?;
+        let response = check_status(self.name, response).await?;
+        let mime_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok())
+            .unwrap_or("audio/mpeg").to_string();
+        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+        Ok((bytes.to_vec(), mime_type))
+    }
+}
+
+/// A file sent to the image and audio endpoints. OpenAI detects the format from the file name.
+pub struct FileUpload {
+    pub file_name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
 
-        let response = self
-            .client
-            .post("https://api.openai.com/v1/images/generations")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+impl FileUpload {
+    fn part(self) -> Result<reqwest::multipart::Part, String> {
+        reqwest::multipart::Part::bytes(self.data).file_name(self.file_name).mime_str(&self.mime_type).map_err(|e| e.to_string())
+    }
+}
+
+/// Settings shared by the image endpoints. `None` leaves the API default; not every model accepts every setting.
+#[derive(Debug, Clone, Default)]
+pub struct ImageOptions {
+    pub model: String,
+    pub size: Option<String>,
+    pub quality: Option<String>,
+    /// `vivid` or `natural`, DALL-E 3 only.
+    pub style: Option<String>,
+    /// `url` or `b64_json`.
+    pub response_format: Option<String>,
+    pub n: Option<u32>,
+}
 
-        let result: serde_json::Value = response.json().await?;
-        Ok(result)
+impl ImageOptions {
+    /// Multipart fields for the edit and variation endpoints, which don't take a style.
+    fn form(&self) -> reqwest::multipart::Form {
+        let mut form = reqwest::multipart::Form::new().text("model", self.model.clone());
+        for (key, value) in [("size", &self.size), ("quality", &self.quality), ("response_format", &self.response_format)] {
+            if let Some(value) = value {
+                form = form.text(key, value.clone());
+            }
+        }
+        if let Some(n) = self.n {
+            form = form.text("n", n.to_string());
+        }
+        form
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAiRequest {
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptionOptions {
     pub model: String,
-    pub messages: Vec<OpenAiMessage>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// ISO-639-1 code of the spoken language. Ignored when translating.
+    pub language: Option<String>,
+    /// Text to guide the style or spelling of the transcript.
+    pub prompt: Option<String>,
+    /// `json`, `text`, `srt`, `verbose_json` or `vtt`.
+    pub response_format: Option<String>,
     pub temperature: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub max_tokens: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tools: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_choice: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenAiMessage {
-    pub role: String,
-    pub content: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_calls: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_call_id: Option<String>,
+#[async_trait]
+impl LlmProvider for OpenAiClient {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn chat(&self, request: ChatRequest) -> Result<ChatResponse, String> {
+        let response = self.post("/chat/completions").json(&Self::chat_body(&request, false)).send().await.map_err(|e| e.to_string())?;
+        let raw: serde_json::Value = check_status(self.name, response).await?.json().await.map_err(|e| e.to_string())?;
+
+        let choice = raw.get("choices").and_then(|c| c.get(0)).ok_or("Invalid LLM response")?;
+        let message = choice.get("message").ok_or("Invalid LLM response")?;
+        Ok(ChatResponse {
+            message: message_from_wire(message),
+            finish_reason: choice.get("finish_reason").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            usage: raw.get("usage").and_then(usage_from_wire),
+            raw,
+        })
+    }
+
+    async fn chat_stream(&self, request: ChatRequest, deltas: UnboundedSender<ChatDelta>) -> Result<ChatResponse, String> {
+        let response = self.post("/chat/completions").json(&Self::chat_body(&request, true)).send().await.map_err(|e| e.to_string())?;
+        let response = check_status(self.name, response).await?;
+
+        let mut message = ChatMessage::assistant("");
+        let mut finish_reason = None;
+        let mut usage = None;
+        let mut id = serde_json::Value::Null;
+        read_sse(response, |data| {
+            if data == "[DONE]" {
+                return Ok(false);
+            }
+            let chunk: serde_json::Value = serde_json::from_str(data).map_err(|e| format!("Invalid stream chunk: {}", e))?;
+            if let Some(error) = chunk.get("error") {
+                return Err(format!("{} API error: {}", self.name, error.get("message").and_then(|m| m.as_str()).unwrap_or(&error.to_string())));
+            }
+            if id.is_null() && let Some(v) = chunk.get("id") {
+                id = v.clone();
+            }
+            if let Some(u) = chunk.get("usage").and_then(usage_from_wire) {
+                usage = Some(u);
+            }
+            let Some(choice) = chunk.get("choices").and_then(|c| c.get(0)) else { return Ok(true); };
+            if let Some(reason) = choice.get("finish_reason").and_then(|v| v.as_str()) {
+                finish_reason = Some(reason.to_string());
+            }
+            let Some(delta) = choice.get("delta") else { return Ok(true); };
+
+            if let Some(text) = delta.get("content").and_then(|v| v.as_str()).filter(|s| !s.is_empty()) {
+                message.content.push_str(text);
+                let _ = deltas.send(ChatDelta::Text(text.to_string()));
+            }
+            for call in delta.get("tool_calls").and_then(|v| v.as_array()).into_iter().flatten() {
+                let index = call.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                let call_id = call.get("id").and_then(|v| v.as_str()).map(|s| s.to_string());
+                let name = call.get("function").and_then(|f| f.get("name")).and_then(|v| v.as_str()).map(|s| s.to_string());
+                let arguments = call.get("function").and_then(|f| f.get("arguments")).and_then(|v| v.as_str()).unwrap_or("").to_string();
+
+                if message.tool_calls.len() <= index {
+                    message.tool_calls.resize_with(index + 1, ToolCall::default);
+                }
+                let entry = &mut message.tool_calls[index];
+                if let Some(call_id) = &call_id {
+                    entry.id = call_id.clone();
+                }
+                if let Some(name) = &name {
+                    entry.name.push_str(name);
+                }
+                entry.arguments.push_str(&arguments);
+                let _ = deltas.send(ChatDelta::ToolCall { index, id: call_id, name, arguments });
+            }
+            Ok(true)
+        }).await?;
+
+        let raw = serde_json::json!({
+            "id": id,
+            "object": "chat.completion",
+            "model": request.model,
+            "choices": [{ "index": 0, "message": message_to_wire(&message), "finish_reason": finish_reason }],
+            "usage": usage.as_ref().map(|u: &TokenUsage| serde_json::json!({
+                "prompt_tokens": u.input_tokens,
+                "completion_tokens": u.output_tokens,
+                "total_tokens": u.input_tokens + u.output_tokens,
+            })),
+        });
+        Ok(ChatResponse { message, finish_reason, usage, raw })
+    }
+
+    /// Sends the inputs in batches of [`EMBEDDINGS_BATCH_SIZE`] and adds up the tokens of all batches.
+    async fn embeddings(&self, model: &str, inputs: Vec<String>, dimensions: Option<u32>) -> Result<Embeddings, String> {
+        let mut embeddings = Embeddings { vectors: Vec::with_capacity(inputs.len()), usage: None };
+        for batch in inputs.chunks(EMBEDDINGS_BATCH_SIZE) {
+            let mut body = serde_json::json!({ "model": model, "input": batch, "encoding_format": "float" });
+            if let Some(dimensions) = dimensions {
+                body["dimensions"] = serde_json::json!(dimensions);
+            }
+            let response = self.post("/embeddings").json(&body).send().await.map_err(|e| e.to_string())?;
+            let raw: serde_json::Value = check_status(self.name, response).await?.json().await.map_err(|e| e.to_string())?;
+
+            let mut data: Vec<&serde_json::Value> = raw.get("data").and_then(|v| v.as_array()).ok_or("Invalid embeddings response")?.iter().collect();
+            if data.len() != batch.len() {
+                return Err(format!("Expected {} embeddings, got {}", batch.len(), data.len()));
+            }
+            data.sort_by_key(|d| d.get("index").and_then(|v| v.as_u64()).unwrap_or(0));
+            for d in data {
+                let vector = d.get("embedding")
+                    .and_then(|v| v.as_array())
+                    .map(|v| v.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+                    .ok_or("Invalid embeddings response")?;
+                embeddings.vectors.push(vector);
+            }
+            if let Some(usage) = raw.get("usage").and_then(usage_from_wire) {
+                let total = embeddings.usage.get_or_insert_with(TokenUsage::default);
+                total.input_tokens += usage.input_tokens;
+                total.output_tokens += usage.output_tokens;
+            }
+        }
+        Ok(embeddings)
+    }
+}
+
+fn message_to_wire(message: &ChatMessage) -> serde_json::Value {
+    let mut wire = serde_json::json!({ "role": message.role, "content": message.content });
+    if !message.parts.is_empty() {
+        let text = (!message.content.is_empty()).then(|| serde_json::json!({ "type": "text", "text": message.content }));
+        wire["content"] = text.into_iter().chain(message.parts.iter().map(part_to_wire)).collect();
+    }
+    if !message.tool_calls.is_empty() {
+        wire["tool_calls"] = message.tool_calls.iter().map(|c| serde_json::json!({
+            "id": c.id,
+            "type": "function",
+            "function": { "name": c.name, "arguments": c.arguments }
+        })).collect();
+    }
+    if let Some(id) = &message.tool_call_id {
+        wire["tool_call_id"] = serde_json::json!(id);
+    }
+    wire
+}
+
+fn part_to_wire(part: &ContentPart) -> serde_json::Value {
+    let image = |url: String, detail: &Option<String>| {
+        let mut image_url = serde_json::json!({ "url": url });
+        if let Some(detail) = detail {
+            image_url["detail"] = serde_json::json!(detail);
+        }
+        serde_json::json!({ "type": "image_url", "image_url": image_url })
+    };
+    match part {
+        ContentPart::Text { text } => serde_json::json!({ "type": "text", "text": text }),
+        ContentPart::ImageUrl { url, detail } => image(url.clone(), detail),
+        ContentPart::ImageBase64 { media_type, data, detail } => image(format!("data:{};base64,{}", media_type, data), detail),
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct OpenAiResponse {
-    pub choices: Vec<OpenAiChoice>,
+fn message_from_wire(wire: &serde_json::Value) -> ChatMessage {
+    let str_field = |v: &serde_json::Value, key: &str| v.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+    ChatMessage {
+        role: str_field(wire, "role"),
+        content: str_field(wire, "content"),
+        tool_calls: wire.get("tool_calls").and_then(|v| v.as_array()).into_iter().flatten().map(|c| {
+            let function = c.get("function").cloned().unwrap_or_default();
+            ToolCall {
+                id: str_field(c, "id"),
+                name: str_field(&function, "name"),
+                arguments: match function.get("arguments") {
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    Some(v) => v.to_string(),
+                    None => "{}".to_string(),
+                },
+            }
+        }).collect(),
+        tool_call_id: wire.get("tool_call_id").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        parts: Vec::new(),
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct OpenAiChoice {
-    pub message: OpenAiMessage,
+fn usage_from_wire(usage: &serde_json::Value) -> Option<TokenUsage> {
+    Some(TokenUsage {
+        input_tokens: usage.get("prompt_tokens")?.as_u64()?,
+        output_tokens: usage.get("completion_tokens").and_then(|v| v.as_u64()).unwrap_or(0),
+    })
 }
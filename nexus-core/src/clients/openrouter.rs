@@ -1,77 +1,13 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Clone)]
-pub struct OpenRouterClient {
-    api_key: String,
-    client: reqwest::Client,
-}
-
-impl OpenRouterClient {
-    pub fn new(api_key: String) -> Self {
-        Self {
-            api_key,
-            client: reqwest::Client::new(),
-        }
-    }
-
-    pub async fn generate(
-        &self,
-        request: OpenRouterRequest,
-    ) -> Result<serde_json::Value, reqwest::Error> {
-        let response = self
-            .client
-            .post("https://openrouter.ai/api/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .header("HTTP-Referer", "https://nexus-workflow.io")
-            .header("X-Title", "Nexus Workflow")
-            .json(&request)
-            .send()
-            .await?;
-
-        let result: serde_json::Value = response.json().await?;
-        Ok(result)
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenRouterRequest {
-    pub model: String,
-    pub messages: Vec<OpenRouterMessage>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub temperature: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "max_tokens")]
-    pub max_tokens: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "top_p")]
-    pub top_p: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "frequency_penalty")]
-    pub frequency_penalty: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "presence_penalty")]
-    pub presence_penalty: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none", rename = "response_format")]
-    pub response_format: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tools: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_choice: Option<serde_json::Value>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OpenRouterMessage {
-    pub role: String,
-    pub content: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_calls: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_call_id: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct OpenRouterResponse {
-    pub choices: Vec<OpenRouterChoice>,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct OpenRouterChoice {
-    pub message: OpenRouterMessage,
+use super::llm::ProviderSettings;
+use super::OpenAiClient;
+
+pub const DEFAULT_BASE_URL: &str = "https://openrouter.ai/api/v1";
+
+/// OpenRouter speaks the OpenAI chat completions API; it only needs its own base URL and attribution headers.
+pub fn client(mut settings: ProviderSettings) -> OpenAiClient {
+    settings.base_url.get_or_insert_with(|| DEFAULT_BASE_URL.to_string());
+    OpenAiClient::new(settings)
+        .named("openrouter")
+        .with_header("HTTP-Referer", "https://nexus-workflow.io")
+        .with_header("X-Title", "Nexus Workflow")
 }
@@ -9,6 +9,8 @@ pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
             description TEXT NOT NULL,
             nodes JSONB NOT NULL,
             edges JSONB NOT NULL,
+            active BOOLEAN NOT NULL DEFAULT FALSE,
+            settings JSONB NOT NULL DEFAULT '{}',
             created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
             updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
         )
@@ -17,6 +19,23 @@ pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
     .execute(pool)
     .await?;
 
+    let has_active = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM information_schema.columns WHERE table_name = 'workflows' AND column_name = 'active')")
+        .fetch_one(pool)
+        .await?;
+    if !has_active {
+        sqlx::query("ALTER TABLE workflows ADD COLUMN active BOOLEAN NOT NULL DEFAULT FALSE").execute(pool).await?;
+        // Event triggers used to fire for every saved workflow; keep those workflows running after the upgrade
+        sqlx::query(
+            "UPDATE workflows SET active = TRUE WHERE EXISTS (
+                SELECT 1 FROM jsonb_array_elements(nodes) AS node
+                WHERE node->>'kind' IN ('slack-trigger', 'trigger-schedule', 'trigger-webhook')
+            )"
+        )
+        .execute(pool)
+        .await?;
+    }
+    let _ = sqlx::query("ALTER TABLE workflows ADD COLUMN IF NOT EXISTS settings JSONB NOT NULL DEFAULT '{}'").execute(pool).await;
+
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS credentials (
@@ -107,5 +126,189 @@ pub async fn init_db(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
     .execute(pool)
     .await?;
 
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS code_modules (
+            id UUID PRIMARY KEY,
+            name TEXT NOT NULL,
+            language TEXT NOT NULL,
+            description TEXT,
+            version INTEGER NOT NULL DEFAULT 1,
+            code TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            UNIQUE (name, language)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS code_module_versions (
+            module_id UUID NOT NULL REFERENCES code_modules(id) ON DELETE CASCADE,
+            version INTEGER NOT NULL,
+            code TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (module_id, version)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS agent_memory (
+            session_key TEXT PRIMARY KEY,
+            messages JSONB NOT NULL DEFAULT '[]',
+            summary TEXT,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    let _ = sqlx::query("ALTER TABLE executions ADD COLUMN IF NOT EXISTS input_tokens BIGINT NOT NULL DEFAULT 0").execute(pool).await;
+    let _ = sqlx::query("ALTER TABLE executions ADD COLUMN IF NOT EXISTS output_tokens BIGINT NOT NULL DEFAULT 0").execute(pool).await;
+    let _ = sqlx::query("ALTER TABLE executions ADD COLUMN IF NOT EXISTS cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0").execute(pool).await;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS llm_usage (
+            id UUID PRIMARY KEY,
+            execution_id UUID NOT NULL,
+            workflow_id TEXT NOT NULL,
+            workflow_name TEXT NOT NULL,
+            node_id TEXT NOT NULL,
+            node_name TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL,
+            calls INTEGER NOT NULL,
+            input_tokens BIGINT NOT NULL,
+            output_tokens BIGINT NOT NULL,
+            cost_usd DOUBLE PRECISION,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    // Embedding calls of knowledge bases happen outside executions
+    let _ = sqlx::query("ALTER TABLE llm_usage ALTER COLUMN execution_id DROP NOT NULL").execute(pool).await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS llm_usage_execution_idx ON llm_usage (execution_id)").execute(pool).await;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS llm_usage_created_idx ON llm_usage (created_at)").execute(pool).await;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS model_prices (
+            model TEXT PRIMARY KEY,
+            input_per_million DOUBLE PRECISION NOT NULL,
+            output_per_million DOUBLE PRECISION NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS llm_cache (
+            key TEXT PRIMARY KEY,
+            response JSONB NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            expires_at TIMESTAMPTZ NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS knowledge_bases (
+            id UUID PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            description TEXT,
+            embedding_provider TEXT NOT NULL,
+            embedding_model TEXT NOT NULL,
+            dimensions INTEGER,
+            chunk_size INTEGER NOT NULL,
+            chunk_overlap INTEGER NOT NULL,
+            credential_id UUID,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS knowledge_documents (
+            id UUID PRIMARY KEY,
+            knowledge_base_id UUID NOT NULL REFERENCES knowledge_bases(id) ON DELETE CASCADE,
+            file_name TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            status TEXT NOT NULL,
+            error TEXT,
+            chunk_count INTEGER NOT NULL DEFAULT 0,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Embeddings are plain REAL[] so every Postgres can store them; searches cast them to pgvector's
+    // `vector` when the extension is available and fall back to comparing them in Rust otherwise
+    let _ = sqlx::query("CREATE EXTENSION IF NOT EXISTS vector").execute(pool).await;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS knowledge_chunks (
+            id UUID PRIMARY KEY,
+            knowledge_base_id UUID NOT NULL REFERENCES knowledge_bases(id) ON DELETE CASCADE,
+            document_id UUID NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            embedding REAL[] NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS knowledge_chunks_base_idx ON knowledge_chunks (knowledge_base_id)").execute(pool).await;
+    // Documents still processing when the server stopped will never finish
+    let _ = sqlx::query("UPDATE knowledge_documents SET status = 'failed', error = 'Interrupted by a server restart' WHERE status = 'processing'").execute(pool).await;
+
+    // Seed list prices (USD per million tokens) once; edit them through /api/usage/prices
+    sqlx::query(
+        r#"
+        INSERT INTO model_prices (model, input_per_million, output_per_million)
+        SELECT * FROM (VALUES
+            ('gpt-4o', 2.5, 10.0),
+            ('gpt-4o-mini', 0.15, 0.6),
+            ('gpt-4.1', 2.0, 8.0),
+            ('gpt-4.1-mini', 0.4, 1.6),
+            ('gpt-4.1-nano', 0.1, 0.4),
+            ('o3-mini', 1.1, 4.4),
+            ('o4-mini', 1.1, 4.4),
+            ('claude-sonnet-4-5', 3.0, 15.0),
+            ('claude-opus-4-1', 15.0, 75.0),
+            ('claude-haiku-4-5', 1.0, 5.0),
+            ('claude-3-5-haiku', 0.8, 4.0),
+            ('text-embedding-3-small', 0.02, 0.0),
+            ('text-embedding-3-large', 0.13, 0.0)
+        ) AS defaults(model, input_per_million, output_per_million)
+        WHERE NOT EXISTS (SELECT 1 FROM model_prices)
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,75 @@
+use base64::{Engine as _, engine::general_purpose};
+use crate::clients::FileUpload;
+
+/// A file as nodes pass it around, the same shape the form trigger gives uploads.
+pub fn file_output(data: &[u8], mime_type: &str, file_name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "fileName": file_name,
+        "mimeType": mime_type,
+        "data": general_purpose::STANDARD.encode(data),
+        "format": "base64",
+    })
+}
+
+/// Reads the file at `property` (a dot path) of the input: an object with base64 `data`, a `data:` URL or
+/// bare base64. Without a declared type images are recognised from their data; without a name the file is
+/// called `default_name`, with the extension of its type when known.
+pub fn file_from_input(input: &serde_json::Value, property: &str, default_name: &str) -> Result<FileUpload, String> {
+    let value = property.split('.').try_fold(input, |v, key| v.get(key))
+        .ok_or_else(|| format!("Binary property '{}' not found in the input", property))?;
+    let (data, mut mime_type, file_name) = match value {
+        serde_json::Value::Object(obj) => (
+            obj.get("data").and_then(|v| v.as_str()).ok_or_else(|| format!("Binary property '{}' has no base64 'data'", property))?,
+            obj.get("mimeType").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            obj.get("fileName").and_then(|v| v.as_str()).filter(|s| !s.is_empty()),
+        ),
+        serde_json::Value::String(s) => (s.as_str(), None, None),
+        _ => return Err(format!("Binary property '{}' is not a file", property)),
+    };
+    let data = match parse_data_url(data) {
+        Some((media_type, data)) => {
+            mime_type = Some(media_type);
+            data
+        }
+        None => data.to_string(),
+    };
+    let data = decode(&data).ok_or_else(|| format!("Binary property '{}' is not valid base64", property))?;
+    let mime_type = mime_type.or_else(|| super::images::sniff_image_type(&data).map(|t| t.to_string()));
+    let file_name = file_name.map(|s| s.to_string()).unwrap_or_else(|| match mime_type.as_deref().and_then(extension) {
+        Some(ext) => format!("{}.{}", default_name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(default_name), ext),
+        None => default_name.to_string(),
+    });
+    let mime_type = mime_type.unwrap_or_else(|| "application/octet-stream".to_string());
+    Ok(FileUpload { file_name, mime_type, data })
+}
+
+pub fn decode(data: &str) -> Option<Vec<u8>> {
+    let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    general_purpose::STANDARD.decode(data).ok()
+}
+
+/// Splits a base64 `data:` URL into its media type and data.
+pub fn parse_data_url(url: &str) -> Option<(String, String)> {
+    let (meta, data) = url.strip_prefix("data:")?.split_once(',')?;
+    let media_type = meta.strip_suffix(";base64")?;
+    Some((media_type.to_string(), data.to_string()))
+}
+
+/// File extension for the audio and image types the OpenAI endpoints accept.
+pub fn extension(mime_type: &str) -> Option<&'static str> {
+    Some(match mime_type {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "m4a",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        "audio/ogg" => "ogg",
+        "audio/opus" => "opus",
+        "audio/webm" => "webm",
+        "audio/flac" | "audio/x-flac" => "flac",
+        "audio/aac" => "aac",
+        "audio/pcm" => "pcm",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/webp" => "webp",
+        _ => return None,
+    })
+}
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use crate::clients::{ChatRequest, ChatResponse};
+use crate::models::Node;
+
+/// How long cached responses stay valid when the node doesn't set `cacheTtl`.
+const DEFAULT_TTL_SECONDS: i64 = 24 * 60 * 60;
+const DEFAULT_CACHE_DIR: &str = ".nexus/llm-cache";
+/// How often expired responses are deleted from the store.
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Where cached responses are kept: `LLM_CACHE_STORE=postgres` (default) or `disk`, in `LLM_CACHE_DIR`.
+enum Store {
+    Postgres,
+    Disk(PathBuf),
+}
+
+impl Store {
+    fn from_env() -> Self {
+        match std::env::var("LLM_CACHE_STORE").as_deref() {
+            Ok("disk") => Store::Disk(std::env::var("LLM_CACHE_DIR").unwrap_or_else(|_| DEFAULT_CACHE_DIR.to_string()).into()),
+            _ => Store::Postgres,
+        }
+    }
+}
+
+/// Response cache of a node that opted in with `cache: true`.
+pub struct ResponseCache {
+    ttl: chrono::Duration,
+    store: Store,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DiskEntry {
+    expires_at: chrono::DateTime<chrono::Utc>,
+    response: ChatResponse,
+}
+
+impl ResponseCache {
+    pub fn from_node(node: &Node) -> Option<Self> {
+        if !node.config.get("cache").and_then(|v| v.as_bool()).unwrap_or(false) {
+            return None;
+        }
+        let ttl = node.config.get("cacheTtl").and_then(|v| v.as_i64()).filter(|v| *v > 0).unwrap_or(DEFAULT_TTL_SECONDS);
+        Some(Self { ttl: chrono::Duration::seconds(ttl), store: Store::from_env() })
+    }
+
+    /// Fingerprint of a request: provider, endpoint, model, messages, tools and sampling parameters.
+    pub fn key(provider: &str, base_url: &str, request: &ChatRequest) -> String {
+        let fingerprint = serde_json::json!({ "provider": provider, "baseUrl": base_url, "request": request });
+        hex::encode(Sha256::digest(fingerprint.to_string().as_bytes()))
+    }
+
+    pub async fn get(&self, pool: &Pool<Postgres>, key: &str) -> Option<ChatResponse> {
+        match &self.store {
+            Store::Postgres => {
+                let response = sqlx::query_scalar::<_, serde_json::Value>("SELECT response FROM llm_cache WHERE key = $1 AND expires_at > NOW()")
+                    .bind(key)
+                    .fetch_optional(pool)
+                    .await
+                    .ok()??;
+                serde_json::from_value(response).ok()
+            }
+            Store::Disk(dir) => {
+                let path = dir.join(format!("{}.json", key));
+                let entry: DiskEntry = serde_json::from_slice(&tokio::fs::read(&path).await.ok()?).ok()?;
+                if entry.expires_at <= chrono::Utc::now() {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return None;
+                }
+                Some(entry.response)
+            }
+        }
+    }
+
+    /// Stores a response. Failing to write the cache never fails the node.
+    pub async fn put(&self, pool: &Pool<Postgres>, key: &str, response: &ChatResponse) {
+        let expires_at = chrono::Utc::now() + self.ttl;
+        let result = match &self.store {
+            Store::Postgres => sqlx::query(
+                "INSERT INTO llm_cache (key, response, expires_at) VALUES ($1, $2, $3)
+                 ON CONFLICT (key) DO UPDATE SET response = EXCLUDED.response, created_at = NOW(), expires_at = EXCLUDED.expires_at"
+            )
+            .bind(key)
+            .bind(serde_json::to_value(response).unwrap_or_default())
+            .bind(expires_at)
+            .execute(pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+            Store::Disk(dir) => {
+                let entry = DiskEntry { expires_at, response: response.clone() };
+                match tokio::fs::create_dir_all(dir).await {
+                    Ok(()) => tokio::fs::write(dir.join(format!("{}.json", key)), serde_json::to_vec(&entry).unwrap_or_default()).await.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to write LLM cache: {}", e);
+        }
+    }
+}
+
+/// Deletes expired responses now and then every `PURGE_INTERVAL`, so entries that are never read again don't pile up.
+pub fn spawn_purge(pool: Pool<Postgres>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_expired(&pool).await {
+                eprintln!("Failed to purge LLM cache: {}", e);
+            }
+        }
+    });
+}
+
+async fn purge_expired(pool: &Pool<Postgres>) -> Result<(), String> {
+    match Store::from_env() {
+        Store::Postgres => {
+            sqlx::query("DELETE FROM llm_cache WHERE expires_at <= NOW()").execute(pool).await.map_err(|e| e.to_string())?;
+        }
+        Store::Disk(dir) => {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e.to_string()),
+            };
+            let now = chrono::Utc::now();
+            while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let expired = match tokio::fs::read(&path).await {
+                    Ok(bytes) => serde_json::from_slice::<DiskEntry>(&bytes).map(|e| e.expires_at <= now).unwrap_or(true),
+                    Err(_) => false,
+                };
+                if expired {
+                    let _ = tokio::fs::remove_file(&path).await;
+                }
+            }
+        }
+    }
+    Ok(())
+}
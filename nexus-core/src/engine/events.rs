@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use crate::models::NodeExecutionResult;
+
+/// Events buffered per execution before slow subscribers start missing some.
+const EVENT_BUFFER: usize = 1024;
+
+/// How long a reserved execution id waits for its execution to start before the reservation lapses.
+const PENDING_TTL: Duration = Duration::from_secs(60);
+
+/// Something that happened while an execution was running, as sent to `/api/executions/:id/events`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionEvent {
+    NodeStarted { node_id: String, node_name: String },
+    /// A piece of text generated by an LLM or agent node.
+    Token { node_id: String, text: String },
+    /// A fragment of a tool call the model is generating; `arguments` arrive in pieces.
+    ToolCallDelta { node_id: String, index: usize, id: Option<String>, name: Option<String>, arguments: String },
+    NodeFinished { result: NodeExecutionResult },
+    /// Last event of an execution. `output` is the output of the last node that ran successfully.
+    Finished { status: String, output: Option<serde_json::Value> },
+}
+
+struct Channel {
+    tx: broadcast::Sender<ExecutionEvent>,
+    /// False while the id is only reserved; see [`reserve`].
+    started: bool,
+}
+
+impl Channel {
+    fn new(started: bool) -> Self {
+        Self { tx: broadcast::channel(EVENT_BUFFER).0, started }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CHANNELS: Mutex<HashMap<Uuid, Channel>> = Mutex::new(HashMap::new());
+}
+
+/// Reserves the id of an execution that is about to be started, so clients can subscribe before it runs.
+/// If no execution is started under it within [`PENDING_TTL`], the reservation lapses and its subscribers
+/// are disconnected.
+pub fn reserve() -> Uuid {
+    let execution_id = Uuid::new_v4();
+    CHANNELS.lock().unwrap().insert(execution_id, Channel::new(false));
+    tokio::spawn(async move {
+        tokio::time::sleep(PENDING_TTL).await;
+        let mut channels = CHANNELS.lock().unwrap();
+        if channels.get(&execution_id).is_some_and(|c| !c.started) {
+            channels.remove(&execution_id);
+        }
+    });
+    execution_id
+}
+
+/// Marks a reserved execution as started, so its reservation no longer lapses. Returns false when an
+/// execution is already running under this id.
+pub fn start(execution_id: Uuid) -> bool {
+    match CHANNELS.lock().unwrap().get_mut(&execution_id) {
+        Some(channel) if channel.started => false,
+        Some(channel) => {
+            channel.started = true;
+            true
+        }
+        None => true,
+    }
+}
+
+/// Subscribes to the events of a reserved or running execution. `None` when no events will come,
+/// because the execution already finished or was never announced here.
+pub fn subscribe(execution_id: Uuid) -> Option<broadcast::Receiver<ExecutionEvent>> {
+    CHANNELS.lock().unwrap().get(&execution_id).map(|c| c.tx.subscribe())
+}
+
+/// Subscribes to an execution that is starting or known to be running, opening its channel if needed.
+/// The execution must end with [`finish`] (or the channel be [`discard`]ed), which closes the channel.
+pub fn open(execution_id: Uuid) -> broadcast::Receiver<ExecutionEvent> {
+    CHANNELS.lock().unwrap()
+        .entry(execution_id)
+        .or_insert_with(|| Channel::new(true))
+        .tx
+        .subscribe()
+}
+
+/// Closes a channel that [`open`] created for an execution that turned out not to be running.
+pub fn discard(execution_id: Uuid) {
+    CHANNELS.lock().unwrap().remove(&execution_id);
+}
+
+/// Whether anyone is listening, so nodes only pay for streaming when it is shown somewhere.
+pub fn is_watched(execution_id: Uuid) -> bool {
+    CHANNELS.lock().unwrap().get(&execution_id).is_some_and(|c| c.tx.receiver_count() > 0)
+}
+
+pub fn publish(execution_id: Uuid, event: ExecutionEvent) {
+    if let Some(channel) = CHANNELS.lock().unwrap().get(&execution_id) {
+        let _ = channel.tx.send(event);
+    }
+}
+
+/// Sends the final event and closes the stream of every subscriber.
+pub fn finish(execution_id: Uuid, status: &str, results: &[NodeExecutionResult]) {
+    if let Some(channel) = CHANNELS.lock().unwrap().remove(&execution_id) {
+        let _ = channel.tx.send(finished(status, results));
+    }
+}
+
+/// The final event of an execution with the given status and node results.
+pub fn finished(status: &str, results: &[NodeExecutionResult]) -> ExecutionEvent {
+    let output = results.iter().rev().find(|r| r.success).and_then(|r| r.output.clone());
+    ExecutionEvent::Finished { status: status.to_string(), output }
+}
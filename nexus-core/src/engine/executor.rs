@@ -1,44 +1,306 @@
-use rquickjs::{Context, Runtime};
+use rquickjs::{CatchResultExt, Context, Runtime};
 use serde_json::Value;
 use base64::{Engine as _, engine::general_purpose};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 pub struct CodeExecutor;
 
+/// What a Code node run produced: the returned value plus anything it logged.
+#[derive(Debug, Clone)]
+pub struct CodeOutput {
+    pub value: Value,
+    pub logs: Vec<Value>,
+    /// Names of the shared modules the script imported.
+    pub modules: Vec<String>,
+}
+
+/// A failed Code node run: the error plus whatever the script logged before it.
+#[derive(Debug, Clone)]
+pub struct CodeError {
+    pub message: String,
+    pub logs: Vec<Value>,
+}
+
+impl CodeError {
+    /// Prefixes the message, keeping the logs.
+    pub fn context(self, prefix: &str) -> Self {
+        Self { message: format!("{}: {}", prefix, self.message), logs: self.logs }
+    }
+}
+
+impl From<String> for CodeError {
+    fn from(message: String) -> Self {
+        Self { message, logs: Vec::new() }
+    }
+}
+
+/// A shared code module made importable to a Code node run.
+#[derive(Debug, Clone)]
+pub struct ModuleSource {
+    pub name: String,
+    pub version: i32,
+    pub code: String,
+}
+
+/// Upper bound on captured `console.*` entries so a chatty loop can't exhaust memory.
+const MAX_LOG_ENTRIES: usize = 1000;
+
+/// Cap on what a WASM module may write to stdout or stderr.
+const WASM_OUTPUT_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Interval of the thread that advances the WASM engine epoch; timeouts are counted in these ticks.
+const WASM_EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Compiled modules kept around, keyed by content hash, so repeated runs skip compilation.
+const WASM_MODULE_CACHE_SIZE: usize = 32;
+
+lazy_static::lazy_static! {
+    static ref WASM_ENGINE: wasmtime::Engine = {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        config.epoch_interruption(true);
+        let engine = wasmtime::Engine::new(&config).expect("valid WASM engine config");
+        let ticker = engine.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(WASM_EPOCH_TICK);
+            ticker.increment_epoch();
+        });
+        engine
+    };
+    static ref WASM_MODULES: Mutex<HashMap<String, wasmtime::Module>> = Mutex::new(HashMap::new());
+}
+
+struct WasmState {
+    wasi: wasmtime_wasi::preview1::WasiP1Ctx,
+    limits: wasmtime::StoreLimits,
+}
+
+/// Resource limits applied to a single Code node run.
+#[derive(Debug, Clone, Copy)]
+pub struct CodeLimits {
+    pub memory_bytes: usize,
+    pub stack_bytes: usize,
+    pub timeout: Duration,
+    /// Instruction budget for WASM modules.
+    pub fuel: u64,
+}
+
+impl Default for CodeLimits {
+    /// Defaults can be tuned per deployment through `CODE_MEMORY_LIMIT_MB`, `CODE_STACK_LIMIT_KB`, `CODE_TIMEOUT_MS` and `CODE_WASM_FUEL`.
+    fn default() -> Self {
+        Self {
+            memory_bytes: (env_u64("CODE_MEMORY_LIMIT_MB", 64) * 1024 * 1024) as usize,
+            stack_bytes: (env_u64("CODE_STACK_LIMIT_KB", 1024) * 1024) as usize,
+            timeout: Duration::from_millis(env_u64("CODE_TIMEOUT_MS", 5000)),
+            fuel: env_u64("CODE_WASM_FUEL", 1_000_000_000),
+        }
+    }
+}
+
+impl CodeLimits {
+    /// Reads `memoryLimitMb`, `stackLimitKb`, `timeoutMs` and `fuel` from a Code node config, falling back to the defaults.
+    pub fn from_config(config: &Value) -> Self {
+        Self::from_config_or(config, Self::default())
+    }
+
+    /// Like [`CodeLimits::from_config`], but the memory cap is the address space of the whole Python process,
+    /// interpreter and imported libraries included, so it defaults to `CODE_PYTHON_MEMORY_LIMIT_MB` (512 MB).
+    pub fn python_from_config(config: &Value) -> Self {
+        let defaults = Self { memory_bytes: (env_u64("CODE_PYTHON_MEMORY_LIMIT_MB", 512) * 1024 * 1024) as usize, ..Self::default() };
+        Self::from_config_or(config, defaults)
+    }
+
+    fn from_config_or(config: &Value, defaults: Self) -> Self {
+        Self {
+            memory_bytes: config.get("memoryLimitMb").and_then(|v| v.as_u64()).map(|mb| (mb * 1024 * 1024) as usize).unwrap_or(defaults.memory_bytes),
+            stack_bytes: config.get("stackLimitKb").and_then(|v| v.as_u64()).map(|kb| (kb * 1024) as usize).unwrap_or(defaults.stack_bytes),
+            timeout: config.get("timeoutMs").and_then(|v| v.as_u64()).map(Duration::from_millis).unwrap_or(defaults.timeout),
+            fuel: config.get("fuel").and_then(|v| v.as_u64()).unwrap_or(defaults.fuel),
+        }
+    }
+}
+
 impl CodeExecutor {
-    pub async fn execute_js(code: &str, input: &Value) -> Result<Value, String> {
+    /// Runs a JS Code node on the blocking pool so a runaway script can't stall a tokio worker.
+    /// `context` carries the `execution`, `workflow` and per-node `nodes` outputs behind the `$`-helpers,
+    /// and `modules` can be loaded with `await import("name")`.
+    pub async fn execute_js(code: &str, input: &Value, context: &Value, modules: &[ModuleSource], limits: CodeLimits) -> Result<CodeOutput, CodeError> {
+        let code = code.to_string();
+        let input = input.clone();
+        let context = context.clone();
+        let modules = modules.to_vec();
+        tokio::task::spawn_blocking(move || Self::run_js(&code, &input, &context, &modules, limits))
+            .await
+            .map_err(|e| format!("JS worker failed: {}", e))?
+    }
+
+    fn run_js(code: &str, input: &Value, code_context: &Value, modules: &[ModuleSource], limits: CodeLimits) -> Result<CodeOutput, CodeError> {
         let runtime = Runtime::new().map_err(|e| e.to_string())?;
+        runtime.set_memory_limit(limits.memory_bytes);
+        runtime.set_max_stack_size(limits.stack_bytes);
+
+        // QuickJS polls the interrupt handler while running bytecode; returning true aborts the script
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let deadline = Instant::now() + limits.timeout;
+        let flag = interrupted.clone();
+        runtime.set_interrupt_handler(Some(Box::new(move || {
+            if Instant::now() >= deadline {
+                flag.store(true, Ordering::Relaxed);
+                return true;
+            }
+            false
+        })));
+
+        // Shared modules are served by name through the QuickJS module loader. Each one records
+        // itself when evaluated so the execution knows which versions it actually used.
+        let mut resolver = rquickjs::loader::BuiltinResolver::default();
+        let mut loader = rquickjs::loader::BuiltinLoader::default();
+        for module in modules {
+            let name = serde_json::to_string(&module.name).unwrap_or_default();
+            resolver.add_module(module.name.clone());
+            loader.add_module(module.name.clone(), format!("globalThis.__nexusImported.push({});\n{}", name, module.code));
+        }
+        runtime.set_loader(resolver, loader);
+
         let context = Context::full(&runtime).map_err(|e| e.to_string())?;
 
         // Prepare input JSON
         let input_json = serde_json::to_string(input).unwrap_or_else(|_| "{}".to_string());
+        let mut code_context = code_context.clone();
+        if let Some(obj) = code_context.as_object_mut() {
+            obj.insert("env".to_string(), allowed_env());
+        }
+        let context_json = serde_json::to_string(&code_context).unwrap_or_else(|_| "{}".to_string());
         
-        context.with(|ctx| {
+        let result: Result<CodeOutput, CodeError> = context.with(|ctx| {
             let globals = ctx.globals();
             
+            // Native helpers backing `$helpers`; the JS side below wraps them with argument checks
+            let native = rquickjs::Object::new(ctx.clone()).map_err(|e| e.to_string())?;
+            native.set("hash", rquickjs::Function::new(ctx.clone(), |algorithm: String, data: String, encoding: String| hash(&algorithm, &data, &encoding)).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            native.set("base64Encode", rquickjs::Function::new(ctx.clone(), |data: String| general_purpose::STANDARD.encode(data)).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            native.set("base64Decode", rquickjs::Function::new(ctx.clone(), |data: String| {
+                general_purpose::STANDARD.decode(data.trim()).ok().map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+            }).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            native.set("uuid", rquickjs::Function::new(ctx.clone(), || uuid::Uuid::new_v4().to_string()).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            native.set("formatDate", rquickjs::Function::new(ctx.clone(), |iso: String, format: String| format_date(&iso, &format)).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            globals.set("__nexusNative", native).map_err(|e| e.to_string())?;
+
             // Inject input data safely
             // We use JSON.parse on a stringified version to ensure proper object creation in JS
             let input_json_escaped = input_json.replace("\\", "\\\\").replace("'", "\\'");
+            let context_json_escaped = context_json.replace("\\", "\\\\").replace("'", "\\'");
             
             let init_script = format!(
                 r#" 
                 (function() {{ 
                     const rawInput = JSON.parse('{}');
-                    const items = Array.isArray(rawInput) ? rawInput : [rawInput];
-                    
+                    const context = JSON.parse('{}');
+
                     // Map to n8n style [{{ json: {{ ... }} }}] if not already
-                    const normalizedItems = items.map(item => {{
+                    const toItems = (data) => (Array.isArray(data) ? data : [data]).map(item => {{
                         if (item && typeof item === 'object' && 'json' in item) return item;
                         return {{ json: item }};
                     }});
+                    const accessor = (items) => ({{
+                        all: () => items,
+                        first: () => items[0],
+                        last: () => items[items.length - 1],
+                        item: items[0],
+                        json: items[0] ? items[0].json : undefined
+                    }});
+                    const normalizedItems = toItems(rawInput);
+
+                    globalThis.$input = accessor(normalizedItems);
+                    globalThis.$json = normalizedItems[0] ? normalizedItems[0].json : {{}};
+
+                    // Outputs of nodes that already ran in this execution, by node name
+                    const nodeOutputs = context.nodes || {{}};
+                    const nodeAccessor = (name) => {{
+                        if (!Object.prototype.hasOwnProperty.call(nodeOutputs, name)) {{
+                            throw new Error(`Referenced node "${{name}}" has not been executed`);
+                        }}
+                        return accessor(toItems(nodeOutputs[name]));
+                    }};
+                    globalThis.$ = nodeAccessor;
+                    globalThis.$node = new Proxy({{}}, {{
+                        get: (_, name) => typeof name === 'string' ? nodeAccessor(name) : undefined,
+                        has: (_, name) => Object.prototype.hasOwnProperty.call(nodeOutputs, name)
+                    }});
+                    globalThis.$items = (name) => name === undefined ? normalizedItems : nodeAccessor(name).all();
 
-                    globalThis.$input = {{
-                        all: () => normalizedItems,
-                        first: () => normalizedItems[0],
-                        last: () => normalizedItems[normalizedItems.length - 1]
+                    globalThis.$execution = Object.freeze(context.execution || {{}});
+                    globalThis.$workflow = Object.freeze(context.workflow || {{}});
+                    globalThis.$env = Object.freeze(context.env || {{}});
+
+                    const withIso = (date) => {{ date.toISO = date.toISOString; return date; }};
+                    globalThis.$now = withIso(new Date());
+                    const today = new Date();
+                    today.setUTCHours(0, 0, 0, 0);
+                    globalThis.$today = withIso(today);
+
+                    const native = globalThis.__nexusNative;
+                    delete globalThis.__nexusNative;
+                    const toDate = (value) => {{
+                        const date = value instanceof Date ? value : new Date(value);
+                        if (isNaN(date.getTime())) throw new Error(`Invalid date: ${{value}}`);
+                        return date;
+                    }};
+                    const unitMs = {{ milliseconds: 1, seconds: 1000, minutes: 60000, hours: 3600000, days: 86400000, weeks: 604800000 }};
+                    const toUnit = (unit) => {{
+                        if (!(unit in unitMs)) throw new Error(`Unknown unit: ${{unit}}`);
+                        return unitMs[unit];
+                    }};
+                    globalThis.$helpers = Object.freeze({{
+                        base64Encode: (value) => native.base64Encode(String(value)),
+                        base64Decode: (value) => {{
+                            const decoded = native.base64Decode(String(value));
+                            if (decoded == null) throw new Error('Invalid base64 input');
+                            return decoded;
+                        }},
+                        hash: (value, algorithm = 'sha256', encoding = 'hex') => {{
+                            const digest = native.hash(String(algorithm).toLowerCase(), String(value), String(encoding));
+                            if (digest == null) throw new Error(`Unsupported hash algorithm or encoding: ${{algorithm}}/${{encoding}}`);
+                            return digest;
+                        }},
+                        uuid: () => native.uuid(),
+                        parseDate: (value) => withIso(toDate(value)),
+                        formatDate: (value, format = '%Y-%m-%dT%H:%M:%SZ') => {{
+                            const formatted = native.formatDate(toDate(value).toISOString(), String(format));
+                            if (formatted == null) throw new Error(`Invalid date format: ${{format}}`);
+                            return formatted;
+                        }},
+                        addToDate: (value, amount, unit = 'days') => withIso(new Date(toDate(value).getTime() + amount * toUnit(unit))),
+                        dateDiff: (from, to, unit = 'days') => (toDate(to).getTime() - toDate(from).getTime()) / toUnit(unit)
+                    }});
+
+                    // Capture console output so it can be shown next to the node result
+                    const logs = [];
+                    const format = (arg) => {{
+                        if (typeof arg === 'string') return arg;
+                        try {{ return JSON.stringify(arg) ?? String(arg); }} catch (e) {{ return String(arg); }}
+                    }};
+                    const capture = (level) => (...args) => {{
+                        if (logs.length < {}) logs.push({{ level, message: args.map(format).join(' ') }});
+                    }};
+                    globalThis.console = {{
+                        log: capture('log'),
+                        info: capture('info'),
+                        warn: capture('warn'),
+                        error: capture('error'),
+                        debug: capture('debug')
                     }};
+                    globalThis.__nexusLogs = () => JSON.stringify(logs);
+                    globalThis.__nexusImported = [];
                 }})();
                 "#,
-                input_json_escaped
+                input_json_escaped,
+                context_json_escaped,
+                MAX_LOG_ENTRIES
             );
             
             ctx.eval::<(), _>(init_script).map_err(|e| format!("Init Error: {}", e))?;
@@ -47,76 +309,440 @@ impl CodeExecutor {
             // Wrap in an immediately invoked function to support 'return' at top level
             let wrapped_code = format!("(async () => {{ \n{}\n }})()", code);
             
-            match ctx.eval::<rquickjs::Value, _>(wrapped_code) {
-                Ok(res) => {
-                    let json_mod = globals.get::<_, rquickjs::Object>("JSON").map_err(|e| e.to_string())?;
-                    let stringify = json_mod.get::<_, rquickjs::Function>("stringify").map_err(|e| e.to_string())?;
-                    
-                    let json_str: String = stringify.call((res,)).map_err(|e| format!("Serialization Error: {}", e))?;
-                    let val: Value = serde_json::from_str(&json_str).unwrap_or(serde_json::json!({}));
-                    Ok(val)
+            let run = || -> Result<rquickjs::Value, String> {
+                let promise = ctx.eval::<rquickjs::Promise, _>(wrapped_code)
+                    .catch(&ctx)
+                    .map_err(|e| format!("JS Execution Error: {}", e))?;
+
+                // Run the job queue until the async wrapper settles so awaited work completes
+                match promise.finish::<rquickjs::Value>() {
+                    Err(rquickjs::Error::WouldBlock) => Err("JS Execution Error: script is waiting on a promise that never settles".to_string()),
+                    other => other.catch(&ctx).map_err(|e| format!("JS Execution Error: {}", e)),
                 }
-                Err(e) => Err(format!("JS Execution Error: {}", e)),
-            }
-        })
+            };
+            let res = run();
+
+            // Read the logs before a thrown error propagates, so they show up on the failed result too
+            let logs: Vec<Value> = globals.get::<_, rquickjs::Function>("__nexusLogs")
+                .and_then(|logs_fn| logs_fn.call::<_, String>(()))
+                .ok()
+                .and_then(|logs_str| serde_json::from_str(&logs_str).ok())
+                .unwrap_or_default();
+            let res = res.map_err(|message| CodeError { message, logs: logs.clone() })?;
+
+            let json_mod = globals.get::<_, rquickjs::Object>("JSON").map_err(|e| e.to_string())?;
+            let stringify = json_mod.get::<_, rquickjs::Function>("stringify").map_err(|e| e.to_string())?;
+
+            // `undefined` (no return statement) stringifies to nothing
+            let json_str: Option<String> = stringify.call((res,)).map_err(|e| format!("Serialization Error: {}", e))?;
+            let value: Value = json_str.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or(Value::Null);
+
+            let imported = globals.get::<_, Vec<String>>("__nexusImported").unwrap_or_default();
+
+            Ok(CodeOutput { value, logs, modules: imported })
+        });
+
+        result.map_err(|e| CodeError { message: Self::limit_error(e.message, interrupted.load(Ordering::Relaxed), limits), logs: e.logs })
+    }
+
+    /// Rewrites QuickJS errors caused by the runtime limits into a clear "script exceeded limit" message.
+    fn limit_error(error: String, interrupted: bool, limits: CodeLimits) -> String {
+        if interrupted {
+            format!("Script exceeded limit: CPU time limit of {} ms reached", limits.timeout.as_millis())
+        } else if error.contains("out of memory") || error.contains("generated by quickjs: Null") {
+            // When the heap is exhausted QuickJS can't even allocate the error object and throws a bare null
+            format!("Script exceeded limit: memory limit of {} MB reached", limits.memory_bytes / (1024 * 1024))
+        } else if error.contains("stack overflow") || error.contains("Maximum call stack size exceeded") {
+            format!("Script exceeded limit: stack limit of {} KB reached", limits.stack_bytes / 1024)
+        } else {
+            error
+        }
     }
 
-    pub async fn execute_python(code: &str, input: &Value) -> Result<Value, String> {
+    /// Runs a Python Code node in a throwaway working directory. The payload arrives on stdin, the
+    /// script is loaded as the `user_code` module, and CPU/memory are capped with `setrlimit`.
+    /// Shared `modules` are written to a `modules/` directory on the import path.
+    pub async fn execute_python(code: &str, input: &Value, modules: &[ModuleSource], limits: CodeLimits, interpreter: &str) -> Result<CodeOutput, CodeError> {
+        let workdir = std::env::temp_dir().join(format!("nexus-python-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(workdir.join("modules")).await.map_err(|e| format!("Failed to create working directory: {}", e))?;
+
+        let result = match Self::write_python_sources(&workdir, code, modules).await {
+            Ok(()) => Self::run_python(&workdir, input, modules, limits, interpreter).await,
+            Err(e) => Err(format!("Failed to write script: {}", e).into()),
+        };
+
+        let _ = tokio::fs::remove_dir_all(&workdir).await;
+        result
+    }
+
+    async fn write_python_sources(workdir: &Path, code: &str, modules: &[ModuleSource]) -> std::io::Result<()> {
+        tokio::fs::write(workdir.join("user_code.py"), code).await?;
+        for module in modules {
+            tokio::fs::write(workdir.join("modules").join(format!("{}.py", module.name)), &module.code).await?;
+        }
+        Ok(())
+    }
+
+    async fn run_python(workdir: &Path, input: &Value, modules: &[ModuleSource], limits: CodeLimits, interpreter: &str) -> Result<CodeOutput, CodeError> {
         use std::process::Stdio;
+        use tokio::io::AsyncWriteExt;
         use tokio::process::Command;
 
-        let input_json = serde_json::to_string(input).unwrap_or_else(|_| "{}".to_string());
-        let b64_input = general_purpose::STANDARD.encode(input_json);
-        
-        // Python doesn't allow '$' in identifiers. Using 'data' as the variable name.
-        let wrapper = format!(r#" 
-import json
-import sys
-import base64
-
-try:
-    input_str = base64.b64decode('{}').decode('utf-8')
-    data = json.loads(input_str)
-    
-    def main(data):
-        # User code starts here
-{}
-
-    result = main(data)
-    print(json.dumps(result))
-except Exception as e:
-    print(json.dumps({{"error": str(e)}}), file=sys.stderr)
-    sys.exit(1)
-"#,
-            b64_input,
-            indent_code(code)
-        );
-
-        let child = Command::new("python3")
-            .arg("-c")
-            .arg(wrapper)
-            .stdin(Stdio::null())
+        let mut command = Command::new(interpreter);
+        command
+            .args(["-I", "-u", "-X", "utf8", "-c", PYTHON_RUNNER])
+            .current_dir(workdir)
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .env("HOME", workdir)
+            .env("LANG", "C.UTF-8")
+            .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .spawn()
-            .map_err(|e| format!("Failed to spawn python3: {}", e))?;
+            .kill_on_drop(true);
+
+        #[cfg(unix)]
+        sandbox_python(&mut command, limits);
+
+        let mut child = command.spawn().map_err(|e| format!("Failed to spawn {}: {}", interpreter, e))?;
+        #[cfg_attr(not(unix), allow(unused_variables))]
+        let pid = child.id();
+
+        let module_names: Vec<&str> = modules.iter().map(|m| m.name.as_str()).collect();
+        let payload = serde_json::to_vec(&serde_json::json!({ "data": input, "modules": module_names })).map_err(|e| e.to_string())?;
+        if let Some(mut stdin) = child.stdin.take() {
+            tokio::spawn(async move {
+                // The script may exit before reading everything; a broken pipe is reported through its own output
+                let _ = stdin.write_all(&payload).await;
+            });
+        }
+
+        let output = match tokio::time::timeout(limits.timeout, child.wait_with_output()).await {
+            Ok(output) => output.map_err(|e| e.to_string())?,
+            Err(_) => {
+                // Elsewhere `kill_on_drop` stops the interpreter itself
+                #[cfg(unix)]
+                if let Some(pid) = pid {
+                    unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL); }
+                }
+                return Err(format!("Script exceeded limit: time limit of {} ms reached", limits.timeout.as_millis()).into());
+            }
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let Ok(payload) = serde_json::from_str::<Value>(stdout.trim()) else {
+            return Err(if killed_by_limit(&output.status) {
+                format!("Script exceeded limit: CPU time limit of {} ms reached", limits.timeout.as_millis())
+            } else if stderr.contains("MemoryError") {
+                format!("Script exceeded limit: memory limit of {} MB reached", limits.memory_bytes / (1024 * 1024))
+            } else {
+                format!("Python exited with {}: {}", output.status, stderr.trim())
+            }.into());
+        };
+
+        let mut logs: Vec<Value> = payload.get("stdout").and_then(|v| v.as_str()).unwrap_or("")
+            .lines()
+            .map(|line| serde_json::json!({ "level": "log", "message": line }))
+            .collect();
+        logs.extend(stderr.lines().map(|line| serde_json::json!({ "level": "warn", "message": line })));
+        logs.truncate(MAX_LOG_ENTRIES);
+
+        if let Some(error) = payload.get("error").and_then(|v| v.as_str()) {
+            let message = if payload.get("kind").and_then(|v| v.as_str()) == Some("memory") {
+                format!("Script exceeded limit: memory limit of {} MB reached", limits.memory_bytes / (1024 * 1024))
+            } else {
+                error.to_string()
+            };
+            return Err(CodeError { message, logs });
+        }
+
+        let imported = payload.get("modules")
+            .and_then(|v| v.as_array())
+            .map(|names| names.iter().filter_map(|n| n.as_str().map(|s| s.to_string())).collect())
+            .unwrap_or_default();
+
+        Ok(CodeOutput { value: payload.get("result").cloned().unwrap_or(Value::Null), logs, modules: imported })
+    }
+
+    /// Runs a WASI command module (its `_start` export) with the items as JSON on stdin and parses its
+    /// stdout as the result. The module gets no preopened directories, environment or sockets, and is
+    /// stopped when it runs out of fuel or time.
+    pub async fn execute_wasm(module: Vec<u8>, input: &Value, limits: CodeLimits) -> Result<CodeOutput, String> {
+        let input = input.clone();
+        tokio::task::spawn_blocking(move || Self::run_wasm(&module, &input, limits))
+            .await
+            .map_err(|e| format!("WASM worker failed: {}", e))?
+    }
+
+    fn run_wasm(bytes: &[u8], input: &Value, limits: CodeLimits) -> Result<CodeOutput, String> {
+        use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+        use wasmtime_wasi::{I32Exit, WasiCtxBuilder};
+
+        let module = Self::compile_wasm(bytes)?;
 
-        let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+        let stdin = serde_json::to_vec(input).map_err(|e| e.to_string())?;
+        let stdout = MemoryOutputPipe::new(WASM_OUTPUT_LIMIT);
+        let stderr = MemoryOutputPipe::new(WASM_OUTPUT_LIMIT);
+        let wasi = WasiCtxBuilder::new()
+            .args(&["main"])
+            .stdin(MemoryInputPipe::new(stdin))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone())
+            .build_p1();
 
-        if output.status.success() {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let val: Value = serde_json::from_str(stdout.trim()).unwrap_or(serde_json::json!({ "output": stdout.trim() }));
-            Ok(val)
+        let store_limits = wasmtime::StoreLimitsBuilder::new().memory_size(limits.memory_bytes).instances(1).build();
+        let mut store = wasmtime::Store::new(&WASM_ENGINE, WasmState { wasi, limits: store_limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(limits.fuel).map_err(|e| e.to_string())?;
+        let ticks = limits.timeout.as_millis().div_ceil(WASM_EPOCH_TICK.as_millis()).max(1) as u64;
+        store.set_epoch_deadline(ticks);
+
+        let mut linker = wasmtime::Linker::new(&WASM_ENGINE);
+        wasmtime_wasi::preview1::add_to_linker_sync(&mut linker, |state: &mut WasmState| &mut state.wasi).map_err(|e| e.to_string())?;
+        let instance = linker.instantiate(&mut store, &module).map_err(|e| format!("Failed to instantiate module: {}", e))?;
+        let start = instance.get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(|_| "Module has no `_start` export; build it as a WASI command".to_string())?;
+
+        let outcome = start.call(&mut store, ());
+
+        let stderr = String::from_utf8_lossy(&stderr.contents()).to_string();
+        let mut logs: Vec<Value> = stderr.lines().map(|line| serde_json::json!({ "level": "warn", "message": line })).collect();
+        logs.truncate(MAX_LOG_ENTRIES);
+
+        if let Err(e) = outcome {
+            match (e.downcast_ref::<I32Exit>(), e.downcast_ref::<wasmtime::Trap>()) {
+                (Some(I32Exit(0)), _) => {}
+                (Some(I32Exit(code)), _) => return Err(format!("Module exited with code {}: {}", code, stderr.trim())),
+                (_, Some(wasmtime::Trap::OutOfFuel)) => return Err(format!("Script exceeded limit: fuel limit of {} reached", limits.fuel)),
+                (_, Some(wasmtime::Trap::Interrupt)) => return Err(format!("Script exceeded limit: time limit of {} ms reached", limits.timeout.as_millis())),
+                _ => return Err(format!("Module trapped: {:#}", e)),
+            }
+        }
+
+        let stdout = stdout.contents();
+        let value = if stdout.iter().all(|b| b.is_ascii_whitespace()) {
+            Value::Null
         } else {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            Err(format!("Python Error: {}", stderr.trim()))
+            serde_json::from_slice(&stdout).map_err(|e| format!("Module output is not valid JSON: {}", e))?
+        };
+
+        Ok(CodeOutput { value, logs, modules: Vec::new() })
+    }
+
+    fn compile_wasm(bytes: &[u8]) -> Result<wasmtime::Module, String> {
+        use sha2::Digest;
+        let key = hex::encode(sha2::Sha256::digest(bytes));
+        if let Some(module) = WASM_MODULES.lock().unwrap().get(&key) {
+            return Ok(module.clone());
         }
+
+        let module = wasmtime::Module::new(&WASM_ENGINE, bytes).map_err(|e| format!("Invalid WASM module: {}", e))?;
+        let mut modules = WASM_MODULES.lock().unwrap();
+        if modules.len() >= WASM_MODULE_CACHE_SIZE {
+            modules.clear();
+        }
+        modules.insert(key, module.clone());
+        Ok(module)
+    }
+
+    /// Reads the module of a `wasm` Code node, from `modulePath` when `moduleSource` is `file`
+    /// or from the base64 `module` config otherwise.
+    pub async fn load_wasm_module(config: &Value) -> Result<Vec<u8>, String> {
+        match config.get("moduleSource").and_then(|v| v.as_str()).unwrap_or("base64") {
+            "file" => {
+                let path = config.get("modulePath").and_then(|v| v.as_str()).ok_or("Module path not specified")?;
+                tokio::fs::read(path).await.map_err(|e| format!("Failed to read module {}: {}", path, e))
+            }
+            "base64" => {
+                let module = config.get("module").and_then(|v| v.as_str()).ok_or("Module not specified")?;
+                general_purpose::STANDARD.decode(module.trim()).map_err(|e| format!("Invalid base64 module: {}", e))
+            }
+            other => Err(format!("Unsupported module source: {}", other)),
+        }
+    }
+
+    /// Interpreter for a workflow's Python Code nodes. With `useVirtualenv` each workflow gets its own
+    /// virtualenv under `PYTHON_VENV_DIR`, created on first use, so packages installed for one workflow
+    /// don't leak into others. `requirements` (pip requirement lines) are installed into it once per
+    /// distinct set.
+    pub async fn python_interpreter(workflow_id: &str, use_virtualenv: bool, requirements: &str) -> Result<String, String> {
+        let python = std::env::var("PYTHON_BIN").unwrap_or_else(|_| "python3".to_string());
+        if !use_virtualenv || workflow_id.is_empty() {
+            return Ok(python);
+        }
+
+        let root = std::env::var("PYTHON_VENV_DIR").map(PathBuf::from).unwrap_or_else(|_| std::env::temp_dir().join("nexus-venvs"));
+        let venv = root.join(workflow_id.replace(['/', '\\', '.'], "_"));
+        let venv_python = if cfg!(windows) { venv.join("Scripts").join("python.exe") } else { venv.join("bin").join("python") };
+
+        // Nodes of one workflow can run at the same time; only one of them may create or install into the venv
+        let lock = VENV_LOCKS.lock().unwrap().entry(venv.clone()).or_default().clone();
+        let _guard = lock.lock().await;
+
+        if !tokio::fs::try_exists(&venv_python).await.unwrap_or(false) {
+            run_setup_command(tokio::process::Command::new(&python).args(["-m", "venv"]).arg(&venv), "create virtualenv").await?;
+        }
+
+        let requirements: Vec<&str> = requirements.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).collect();
+        if !requirements.is_empty() {
+            use sha2::Digest;
+            let list = requirements.join("\n");
+            let marker = venv.join(format!(".requirements-{}", &hex::encode(sha2::Sha256::digest(&list))[..16]));
+            if !tokio::fs::try_exists(&marker).await.unwrap_or(false) {
+                let file = venv.join("requirements.txt");
+                tokio::fs::write(&file, &list).await.map_err(|e| format!("Failed to write requirements: {}", e))?;
+                run_setup_command(
+                    tokio::process::Command::new(&venv_python).args(["-m", "pip", "install", "--disable-pip-version-check", "-q", "-r"]).arg(&file),
+                    "install requirements",
+                ).await?;
+                tokio::fs::write(&marker, &list).await.map_err(|e| format!("Failed to write requirements: {}", e))?;
+            }
+        }
+
+        Ok(venv_python.to_string_lossy().to_string())
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref VENV_LOCKS: std::sync::Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>> = Default::default();
+}
+
+async fn run_setup_command(command: &mut tokio::process::Command, action: &str) -> Result<(), String> {
+    let output = command.output().await.map_err(|e| format!("Failed to {}: {}", action, e))?;
+    if !output.status.success() {
+        return Err(format!("Failed to {}: {}", action, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// Environment variables Code nodes may read through `$env`, listed in `CODE_ENV_ALLOWLIST` (comma-separated).
+fn allowed_env() -> Value {
+    let allowlist = std::env::var("CODE_ENV_ALLOWLIST").unwrap_or_default();
+    let vars: serde_json::Map<String, Value> = allowlist
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| std::env::var(name).ok().map(|value| (name.to_string(), Value::String(value))))
+        .collect();
+    Value::Object(vars)
+}
+
+/// Digest of `data` for `$helpers.hash`, `None` for unknown algorithms or encodings.
+fn hash(algorithm: &str, data: &str, encoding: &str) -> Option<String> {
+    use sha2::Digest;
+    let digest: Vec<u8> = match algorithm {
+        "md5" => md5::Md5::digest(data).to_vec(),
+        "sha1" => sha1::Sha1::digest(data).to_vec(),
+        "sha256" => sha2::Sha256::digest(data).to_vec(),
+        "sha384" => sha2::Sha384::digest(data).to_vec(),
+        "sha512" => sha2::Sha512::digest(data).to_vec(),
+        _ => return None,
+    };
+    match encoding {
+        "hex" => Some(hex::encode(digest)),
+        "base64" => Some(general_purpose::STANDARD.encode(digest)),
+        _ => None,
     }
 }
 
-fn indent_code(code: &str) -> String {
-    code.lines()
-        .map(|line| format!("        {}", line))
-        .collect::<Vec<_>>()
-        .join("\n")
+/// Formats an ISO timestamp with a strftime pattern for `$helpers.formatDate`.
+fn format_date(iso: &str, format: &str) -> Option<String> {
+    use chrono::format::{Item, StrftimeItems};
+    // chrono panics when displaying an invalid pattern, so reject it up front
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return None;
+    }
+    let date = chrono::DateTime::parse_from_rfc3339(iso).ok()?;
+    Some(date.with_timezone(&chrono::Utc).format(format).to_string())
+}
+
+fn env_u64(key: &str, default: u64) -> u64 {
+    std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(default)
+}
+
+/// Caps the Python process's address space and CPU time and puts it in its own process group.
+#[cfg(unix)]
+fn sandbox_python(command: &mut tokio::process::Command, limits: CodeLimits) {
+    let memory = libc::rlimit { rlim_cur: limits.memory_bytes as libc::rlim_t, rlim_max: limits.memory_bytes as libc::rlim_t };
+    let cpu_seconds = limits.timeout.as_secs_f64().ceil().max(1.0) as libc::rlim_t;
+    let cpu = libc::rlimit { rlim_cur: cpu_seconds, rlim_max: cpu_seconds + 1 };
+    // SAFETY: the closure runs between fork and exec and only calls async-signal-safe libc functions
+    unsafe {
+        command.pre_exec(move || {
+            // Own process group so the wall-clock kill also reaches anything the script spawned
+            libc::setpgid(0, 0);
+            // The resource argument's type differs between libcs, so let it be inferred at each call
+            if libc::setrlimit(libc::RLIMIT_AS, &memory) != 0 || libc::setrlimit(libc::RLIMIT_CPU, &cpu) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
 }
+
+/// Whether the Python process was stopped by its CPU limit or the wall-clock kill.
+#[cfg(unix)]
+fn killed_by_limit(status: &std::process::ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    matches!(status.signal(), Some(libc::SIGXCPU) | Some(libc::SIGKILL))
+}
+
+#[cfg(not(unix))]
+fn killed_by_limit(_status: &std::process::ExitStatus) -> bool {
+    false
+}
+
+/// Bootstrap passed to `python3 -c`. It reads `{"data", "modules"}` from stdin, loads `user_code.py` from the
+/// working directory as a module and writes `{"result"|"error", "stdout", "modules"}` to the original stdout.
+/// Scripts without a `main` function have their top-level statements wrapped in `def main(data)`,
+/// which keeps a bare `return data` working. Anything printed, including by child processes, is
+/// redirected to a file so it can't corrupt the result.
+const PYTHON_RUNNER: &str = r#"
+import ast, asyncio, json, os, sys, traceback, types
+
+result_out = os.fdopen(os.dup(1), "w", encoding="utf-8")
+capture = open("stdout.log", "w+b")
+os.dup2(capture.fileno(), 1)
+
+shared = []
+
+def emit(payload, code):
+    sys.stdout.flush()
+    capture.seek(0)
+    payload["stdout"] = capture.read().decode("utf-8", "replace")
+    payload["modules"] = [name for name in shared if name in sys.modules]
+    result_out.write(json.dumps(payload, default=str))
+    result_out.flush()
+    os._exit(code)
+
+path = os.path.join(os.getcwd(), "user_code.py")
+try:
+    request = json.load(sys.stdin)
+    data = request["data"]
+    shared = request.get("modules", [])
+    sys.path.insert(0, os.path.join(os.getcwd(), "modules"))
+    with open(path, encoding="utf-8") as f:
+        tree = ast.parse(f.read(), filename=path)
+    if not any(isinstance(n, (ast.FunctionDef, ast.AsyncFunctionDef)) and n.name == "main" for n in tree.body):
+        wrapper = ast.parse("def main(data):\n    pass").body[0]
+        wrapper.body = tree.body or [ast.Pass()]
+        tree.body = [wrapper]
+    ast.fix_missing_locations(tree)
+
+    module = types.ModuleType("user_code")
+    module.__file__ = path
+    sys.modules["user_code"] = module
+    exec(compile(tree, path, "exec"), module.__dict__)
+
+    result = module.main(data)
+    if asyncio.iscoroutine(result):
+        result = asyncio.run(result)
+    emit({"result": result}, 0)
+except MemoryError:
+    emit({"error": "MemoryError", "kind": "memory"}, 1)
+except BaseException as e:
+    frames = [f for f in traceback.extract_tb(e.__traceback__) if f.filename == path]
+    location = " (line {})".format(frames[-1].lineno) if frames else ""
+    emit({"error": "{}: {}{}".format(type(e).__name__, e, location)}, 1)
+"#;
@@ -0,0 +1,75 @@
+use crate::clients::ContentPart;
+use crate::models::Node;
+use super::binary::{decode, parse_data_url};
+use super::interpolate_value;
+
+/// Images attached to an LLM node's prompt, from its `images` config. Each entry either points at a URL
+/// (`{ "source": "url", "url": "{{ $input.imageUrl }}" }`) or at a binary property of the input
+/// (`{ "source": "binary", "property": "data" }`), as produced by the form trigger, `extract-from-file`
+/// or an HTTP request returning a file.
+pub fn image_parts(node: &Node, input: &serde_json::Value) -> Result<Vec<ContentPart>, String> {
+    let Some(entries) = node.config.get("images").and_then(|v| v.as_array()) else { return Ok(Vec::new()) };
+    let mut parts = Vec::new();
+    for entry in entries {
+        let detail = entry.get("detail").and_then(|v| v.as_str()).filter(|s| !s.is_empty() && *s != "auto").map(|s| s.to_string());
+        match entry.get("source").and_then(|v| v.as_str()).unwrap_or("url") {
+            "url" => {
+                let url = interpolate_value(entry.get("url").and_then(|v| v.as_str()).unwrap_or(""), input);
+                let url = url.trim();
+                if url.is_empty() { continue; }
+                parts.push(match parse_data_url(url) {
+                    Some((media_type, data)) => ContentPart::ImageBase64 { media_type, data, detail },
+                    None => ContentPart::ImageUrl { url: url.to_string(), detail },
+                });
+            }
+            "binary" => {
+                let property = entry.get("property").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or("data");
+                let mime_type = entry.get("mimeType").and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+                let value = property.split('.').try_fold(input, |v, key| v.get(key))
+                    .ok_or_else(|| format!("Image property '{}' not found in the input", property))?;
+                let values = match value {
+                    serde_json::Value::Array(items) => items.iter().collect(),
+                    other => vec![other],
+                };
+                for value in values {
+                    let (media_type, data) = binary_image(value, mime_type)
+                        .map_err(|e| format!("Image property '{}': {}", property, e))?;
+                    parts.push(ContentPart::ImageBase64 { media_type, data, detail: detail.clone() });
+                }
+            }
+            other => return Err(format!("Unknown image source '{}'", other)),
+        }
+    }
+    Ok(parts)
+}
+
+/// Reads a binary value: an object with base64 `data` (and usually `mimeType`), a `data:` URL or bare base64.
+/// Without a declared type the image format is detected from the data.
+fn binary_image(value: &serde_json::Value, mime_type: Option<&str>) -> Result<(String, String), String> {
+    let (data, declared) = match value {
+        serde_json::Value::Object(obj) => (
+            obj.get("data").and_then(|v| v.as_str()).ok_or("expected base64 'data'")?,
+            obj.get("mimeType").and_then(|v| v.as_str()),
+        ),
+        serde_json::Value::String(s) => (s.as_str(), None),
+        _ => return Err("expected binary data".to_string()),
+    };
+    if let Some((media_type, data)) = parse_data_url(data) {
+        return Ok((media_type, data));
+    }
+    let bytes = decode(data).ok_or("data is not valid base64")?;
+    let media_type = mime_type.or(declared).filter(|t| t.starts_with("image/")).map(|t| t.to_string())
+        .or_else(|| sniff_image_type(&bytes).map(|t| t.to_string()))
+        .ok_or("not a PNG, JPEG, GIF or WebP image")?;
+    Ok((media_type, data.chars().filter(|c| !c.is_whitespace()).collect()))
+}
+
+pub fn sniff_image_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
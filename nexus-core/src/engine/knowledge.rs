@@ -0,0 +1,289 @@
+use std::sync::Arc;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+use crate::clients::{registry, LlmProvider, ProviderSettings};
+use crate::clients::llm::{Embeddings, TokenUsage};
+use crate::models::{Credential, KnowledgeBase, KnowledgeChunkMatch};
+use super::usage;
+
+pub const DEFAULT_PROVIDER: &str = "openai";
+pub const DEFAULT_MODEL: &str = "text-embedding-3-small";
+pub const DEFAULT_CHUNK_SIZE: i32 = 1000;
+pub const DEFAULT_CHUNK_OVERLAP: i32 = 200;
+/// Chunks returned by the `knowledge-retrieval` node and the search endpoint when no `topK` is given.
+pub const DEFAULT_TOP_K: i64 = 4;
+
+pub async fn load(pool: &Pool<Postgres>, id: Uuid) -> Result<KnowledgeBase, String> {
+    sqlx::query_as::<_, KnowledgeBase>("SELECT * FROM knowledge_bases WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Knowledge base {} not found", id))
+}
+
+/// Reads the text of an uploaded file: PDFs page by page, HTML without its markup and anything else as UTF-8 text.
+pub fn extract_text(file_name: &str, mime_type: &str, data: &[u8]) -> Result<String, String> {
+    let extension = file_name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).unwrap_or_default();
+    if mime_type == "application/pdf" || extension == "pdf" {
+        let document = lopdf::Document::load_mem(data).map_err(|e| format!("Could not read PDF: {}", e))?;
+        let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+        return document.extract_text(&pages).map_err(|e| format!("Could not extract text from PDF: {}", e));
+    }
+    let text = std::str::from_utf8(data).map_err(|_| format!("Unsupported file '{}': only PDF and text files can be added", file_name))?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    if mime_type == "text/html" || extension == "html" || extension == "htm" {
+        Ok(strip_html(text))
+    } else {
+        Ok(text.to_string())
+    }
+}
+
+/// Drops tags, scripts and styles and decodes the common entities. Good enough for embedding, not for display.
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let lower = rest.get(..7).unwrap_or(rest).to_ascii_lowercase();
+        let skip_until = if lower.starts_with("<script") { Some("</script>") } else if lower.starts_with("<style") { Some("</style>") } else { None };
+        let end = match skip_until {
+            Some(closing) => rest.to_ascii_lowercase().find(closing).map(|i| i + closing.len()),
+            None => rest.find('>').map(|i| i + 1),
+        };
+        let Some(end) = end else { rest = ""; break; };
+        // Block-level tags separate words and paragraphs
+        text.push('\n');
+        rest = &rest[end..];
+    }
+    text.push_str(rest);
+    let text = text.replace("&nbsp;", " ").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&#39;", "'").replace("&amp;", "&");
+    text.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+/// Splits text into chunks of at most `size` characters, each starting up to `overlap` characters before the
+/// previous one ended. Chunks end at a paragraph, sentence or word break in their second half when there is one.
+pub fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let size = size.max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + size).min(chars.len());
+        if end < chars.len() {
+            let window_start = start + size / 2;
+            if let Some(cut) = break_point(&chars[window_start..end]) {
+                end = window_start + cut;
+            }
+        }
+        let chunk: String = chars[start..end].iter().collect();
+        let chunk = chunk.trim();
+        if !chunk.is_empty() {
+            chunks.push(chunk.to_string());
+        }
+        if end == chars.len() { break; }
+        let next = end.saturating_sub(overlap).max(start + 1);
+        // Start the overlap at a word rather than in the middle of one
+        start = (next..end).find(|&i| chars[i - 1].is_whitespace()).unwrap_or(next);
+    }
+    chunks
+}
+
+/// Position just after the last paragraph break in `window`, else the last sentence end, else the last whitespace.
+fn break_point(window: &[char]) -> Option<usize> {
+    let last = |is_break: &dyn Fn(usize) -> bool| (1..=window.len()).rev().find(|&i| is_break(i));
+    last(&|i| i >= 2 && window[i - 1] == '\n' && window[i - 2] == '\n')
+        .or_else(|| last(&|i| matches!(window[i - 1], '.' | '!' | '?') && window.get(i).is_none_or(|c| c.is_whitespace())))
+        .or_else(|| last(&|i| window[i - 1].is_whitespace()))
+}
+
+async fn provider(pool: &Pool<Postgres>, kb: &KnowledgeBase) -> Result<Arc<dyn LlmProvider>, String> {
+    let credential = match kb.credential_id {
+        Some(id) => sqlx::query_as::<_, Credential>("SELECT * FROM credentials WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| e.to_string())?,
+        None => None,
+    };
+    registry().create(&kb.embedding_provider, credential.map(|c| ProviderSettings::from_credential(&c.data)).unwrap_or_default())
+}
+
+/// Embeds `inputs` with the knowledge base's provider and model.
+pub async fn embed(pool: &Pool<Postgres>, kb: &KnowledgeBase, inputs: Vec<String>) -> Result<Embeddings, String> {
+    let client = provider(pool, kb).await?;
+    client.embeddings(&kb.embedding_model, inputs, kb.dimensions.map(|d| d as u32)).await
+}
+
+/// Extracts, chunks and embeds an uploaded document in the background, then marks it `ready`
+/// or `failed` with the error.
+pub async fn ingest_document(pool: Pool<Postgres>, kb: KnowledgeBase, document_id: Uuid, file_name: String, mime_type: String, data: Vec<u8>) {
+    let result = match store_chunks(&pool, &kb, document_id, &file_name, &mime_type, &data).await {
+        Ok(count) => sqlx::query("UPDATE knowledge_documents SET status = 'ready', chunk_count = $2 WHERE id = $1")
+            .bind(document_id)
+            .bind(count as i32)
+            .execute(&pool)
+            .await,
+        Err(e) => sqlx::query("UPDATE knowledge_documents SET status = 'failed', error = $2 WHERE id = $1")
+            .bind(document_id)
+            .bind(e)
+            .execute(&pool)
+            .await,
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to update knowledge document {}: {}", document_id, e);
+    }
+}
+
+async fn store_chunks(pool: &Pool<Postgres>, kb: &KnowledgeBase, document_id: Uuid, file_name: &str, mime_type: &str, data: &[u8]) -> Result<usize, String> {
+    let text = extract_text(file_name, mime_type, data)?;
+    let chunks = chunk_text(&text, kb.chunk_size as usize, kb.chunk_overlap as usize);
+    if chunks.is_empty() {
+        return Err("The document contains no text".to_string());
+    }
+    let embeddings = embed(pool, kb, chunks.clone()).await?;
+    if let Some(tokens) = &embeddings.usage {
+        usage::record_knowledge_base(pool, kb, &document_id.to_string(), file_name, tokens).await;
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for (index, (content, embedding)) in chunks.iter().zip(embeddings.vectors).enumerate() {
+        sqlx::query("INSERT INTO knowledge_chunks (id, knowledge_base_id, document_id, chunk_index, content, embedding) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(Uuid::new_v4())
+            .bind(kb.id)
+            .bind(document_id)
+            .bind(index as i32)
+            .bind(content)
+            .bind(embedding)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(chunks.len())
+}
+
+/// The `top_k` chunks most similar to `query`, best first, with the token usage of embedding the query.
+/// Uses pgvector when the extension is installed and compares every chunk of the knowledge base in Rust otherwise.
+pub async fn search(pool: &Pool<Postgres>, kb: &KnowledgeBase, query: &str, top_k: i64) -> Result<(Vec<KnowledgeChunkMatch>, Option<TokenUsage>), String> {
+    let mut embeddings = embed(pool, kb, vec![query.to_string()]).await?;
+    let embedding = embeddings.vectors.pop().ok_or("No embedding returned for the query")?;
+    let matches = nearest_chunks(pool, kb, &embedding, top_k).await?;
+    Ok((matches, embeddings.usage))
+}
+
+async fn nearest_chunks(pool: &Pool<Postgres>, kb: &KnowledgeBase, embedding: &[f32], top_k: i64) -> Result<Vec<KnowledgeChunkMatch>, String> {
+    if has_pgvector(pool).await {
+        return sqlx::query_as::<_, KnowledgeChunkMatch>(
+            "SELECT c.content, (1 - (c.embedding::vector <=> $2::real[]::vector))::DOUBLE PRECISION AS score,
+                    c.document_id, d.file_name AS document_name, c.chunk_index
+             FROM knowledge_chunks c JOIN knowledge_documents d ON d.id = c.document_id
+             WHERE c.knowledge_base_id = $1
+             ORDER BY c.embedding::vector <=> $2::real[]::vector
+             LIMIT $3"
+        )
+        .bind(kb.id)
+        .bind(embedding)
+        .bind(top_k)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string());
+    }
+
+    let rows = sqlx::query_as::<_, (String, Vec<f32>, Uuid, String, i32)>(
+        "SELECT c.content, c.embedding, c.document_id, d.file_name, c.chunk_index
+         FROM knowledge_chunks c JOIN knowledge_documents d ON d.id = c.document_id
+         WHERE c.knowledge_base_id = $1"
+    )
+    .bind(kb.id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut matches: Vec<KnowledgeChunkMatch> = rows.into_iter()
+        .filter(|(_, vector, ..)| vector.len() == embedding.len())
+        .map(|(content, vector, document_id, document_name, chunk_index)| KnowledgeChunkMatch {
+            content,
+            score: cosine_similarity(embedding, &vector),
+            document_id,
+            document_name,
+            chunk_index,
+        })
+        .collect();
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(top_k.max(0) as usize);
+    Ok(matches)
+}
+
+async fn has_pgvector(pool: &Pool<Postgres>) -> bool {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'vector')")
+        .fetch_one(pool)
+        .await
+        .unwrap_or(false)
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f64, 0.0f64, 0.0f64);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (*x as f64, *y as f64);
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 { 0.0 } else { dot / (norm_a.sqrt() * norm_b.sqrt()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_text_keeps_short_text_whole() {
+        assert_eq!(chunk_text("  Hello world.  ", 100, 10), vec!["Hello world."]);
+        assert!(chunk_text("   ", 100, 10).is_empty());
+    }
+
+    #[test]
+    fn chunk_text_breaks_at_sentences_and_overlaps_at_words() {
+        let text = "One two three. Four five six. Seven eight nine.";
+        let chunks = chunk_text(text, 20, 6);
+        assert_eq!(chunks, vec!["One two three.", "three. Four five", "five six. Seven", "Seven eight nine."]);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 20));
+    }
+
+    #[test]
+    fn chunk_text_always_advances() {
+        let chunks = chunk_text("abcdefghij", 3, 5);
+        assert_eq!(chunks, vec!["abc", "bcd", "cde", "def", "efg", "fgh", "ghi", "hij"]);
+    }
+
+    #[test]
+    fn chunk_text_counts_characters_not_bytes() {
+        let chunks = chunk_text("ééé ééé ééé", 4, 0);
+        assert_eq!(chunks, vec!["ééé", "ééé", "ééé"]);
+    }
+
+    #[test]
+    fn break_point_prefers_paragraphs_then_sentences_then_words() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(break_point(&chars("a.\n\nb. c d")), Some(4));
+        assert_eq!(break_point(&chars("a. b? c d")), Some(5));
+        assert_eq!(break_point(&chars("v1.2 next")), Some(5));
+        assert_eq!(break_point(&chars("a b c")), Some(4));
+        assert_eq!(break_point(&chars("abc")), None);
+    }
+
+    #[test]
+    fn strip_html_drops_markup_scripts_and_styles() {
+        let html = "<html><head><style>p { color: red; }</style><SCRIPT>alert('<b>')</SCRIPT></head>\
+                    <body><h1>Title</h1><p>Fish &amp; chips&nbsp;&lt;3</p></body></html>";
+        assert_eq!(strip_html(html), "Title\nFish & chips <3");
+    }
+
+    #[test]
+    fn strip_html_drops_unclosed_tags_and_decodes_once() {
+        assert_eq!(strip_html("before <p unclosed"), "before");
+        assert_eq!(strip_html("&amp;lt;"), "&lt;");
+    }
+}
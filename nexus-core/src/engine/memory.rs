@@ -0,0 +1,175 @@
+use sqlx::{Pool, Postgres};
+use crate::clients::{ChatMessage, ChatRequest, LlmProvider};
+use crate::models::Node;
+use super::ExecutionContext;
+
+/// Node kinds that can be connected to an agent's `memory` port.
+pub const MEMORY_KINDS: [&str; 3] = ["memory-window", "memory-token-buffer", "memory-summary"];
+
+const SUMMARY_PROMPT: &str = "Progressively summarize the conversation, adding onto the previous summary. \
+    Keep names, facts, decisions and open questions. Reply with the new summary only.";
+
+enum Strategy {
+    /// Keeps the last `n` interactions (a user message and the reply to it).
+    Window(usize),
+    /// Keeps as many recent messages as fit in the token budget.
+    TokenBuffer(usize),
+    /// Folds messages that no longer fit in the token budget into a running summary, keeping the last `keep` messages verbatim.
+    Summary { max_tokens: usize, keep: usize },
+}
+
+/// Conversation history of one session, persisted in `agent_memory`.
+pub struct AgentMemory {
+    strategy: Strategy,
+    session_key: String,
+    summary: Option<String>,
+    history: Vec<ChatMessage>,
+    /// Summary as it was loaded, to tell whether another run compacted the session meanwhile.
+    loaded_summary: Option<String>,
+    /// Number of stored messages at the front that are no longer in `history` (trimmed or folded into the summary).
+    dropped: usize,
+}
+
+impl AgentMemory {
+    pub async fn load(pool: &Pool<Postgres>, node: &Node, session_key: String) -> Result<Self, String> {
+        let number = |key: &str, default: usize| node.config.get(key).and_then(|v| v.as_u64()).map(|v| v as usize).filter(|v| *v > 0).unwrap_or(default);
+        let strategy = match node.kind.as_str() {
+            "memory-window" => Strategy::Window(number("windowSize", 5)),
+            "memory-token-buffer" => Strategy::TokenBuffer(number("maxTokens", 2000)),
+            "memory-summary" => Strategy::Summary { max_tokens: number("maxTokens", 2000), keep: number("keepMessages", 4) },
+            other => return Err(format!("Unsupported memory node: {}", other)),
+        };
+
+        let row = sqlx::query_as::<_, (serde_json::Value, Option<String>)>("SELECT messages, summary FROM agent_memory WHERE session_key = $1")
+            .bind(&session_key)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| format!("Failed to load agent memory: {}", e))?;
+        let (history, summary) = match row {
+            Some((messages, summary)) => (serde_json::from_value::<Vec<ChatMessage>>(messages).unwrap_or_default(), summary),
+            None => (Vec::new(), None),
+        };
+
+        let stored = history.len();
+        let mut memory = Self { strategy, session_key, loaded_summary: summary.clone(), summary, history, dropped: 0 };
+        memory.trim();
+        memory.dropped = stored - memory.history.len();
+        Ok(memory)
+    }
+
+    /// The remembered conversation, to be sent after the system prompt and before the new user message.
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        let mut messages = Vec::new();
+        if let Some(summary) = self.summary.as_ref().filter(|s| !s.is_empty()) {
+            messages.push(ChatMessage::system(format!("Summary of the conversation so far:\n{}", summary)));
+        }
+        messages.extend(self.history.iter().cloned());
+        messages
+    }
+
+    /// Summary memory: folds the oldest messages into the summary once the history is over its token budget.
+    /// The summary call is accounted to `agent`.
+    pub async fn compact(&mut self, client: &dyn LlmProvider, model: &str, agent: &Node, ctx: &ExecutionContext) -> Result<(), String> {
+        let Strategy::Summary { max_tokens, keep } = self.strategy else { return Ok(()); };
+        if estimate_tokens(&self.history) <= max_tokens || self.history.len() <= keep {
+            return Ok(());
+        }
+
+        let mut split = self.history.len() - keep;
+        // Don't separate a reply from the message it answers.
+        while split < self.history.len() && self.history[split].role != "user" {
+            split += 1;
+        }
+        let older: Vec<ChatMessage> = self.history.drain(..split).collect();
+        self.dropped += split;
+        let transcript = older.iter().map(|m| format!("{}: {}", m.role, m.content)).collect::<Vec<_>>().join("\n");
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: vec![
+                ChatMessage::system(SUMMARY_PROMPT),
+                ChatMessage::user(format!("Current summary:\n{}\n\nNew lines of conversation:\n{}", self.summary.as_deref().unwrap_or(""), transcript)),
+            ],
+            ..Default::default()
+        };
+        let response = client.chat(request).await.map_err(|e| format!("Failed to summarize memory: {}", e))?;
+        if let Some(usage) = &response.usage {
+            ctx.usage.record(agent, client.name(), model, usage);
+        }
+        self.summary = Some(response.message.content.trim().to_string());
+        Ok(())
+    }
+
+    /// Adds one interaction and stores the trimmed history.
+    /// The session row is locked while saving, and the interaction is appended to the history as currently stored,
+    /// so runs of the same session that overlap don't drop each other's messages.
+    pub async fn save(&mut self, pool: &Pool<Postgres>, user: String, assistant: String) -> Result<(), String> {
+        let error = |e: sqlx::Error| format!("Failed to save agent memory: {}", e);
+        let mut tx = pool.begin().await.map_err(error)?;
+        sqlx::query("INSERT INTO agent_memory (session_key) VALUES ($1) ON CONFLICT (session_key) DO NOTHING")
+            .bind(&self.session_key)
+            .execute(&mut *tx)
+            .await
+            .map_err(error)?;
+        let (messages, stored_summary) = sqlx::query_as::<_, (serde_json::Value, Option<String>)>("SELECT messages, summary FROM agent_memory WHERE session_key = $1 FOR UPDATE")
+            .bind(&self.session_key)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(error)?;
+        let mut history: Vec<ChatMessage> = serde_json::from_value(messages).unwrap_or_default();
+
+        // Messages only get appended between load and save unless another run compacted the session,
+        // in which case its summary wins and this run's compaction is dropped.
+        if stored_summary == self.loaded_summary {
+            history.drain(..self.dropped.min(history.len()));
+        } else {
+            self.summary = stored_summary;
+        }
+        history.push(ChatMessage::user(user));
+        history.push(ChatMessage::assistant(assistant));
+        self.history = history;
+        self.trim();
+
+        sqlx::query("UPDATE agent_memory SET messages = $2, summary = $3, updated_at = NOW() WHERE session_key = $1")
+            .bind(&self.session_key)
+            .bind(serde_json::to_value(&self.history).unwrap_or_default())
+            .bind(&self.summary)
+            .execute(&mut *tx)
+            .await
+            .map_err(error)?;
+        tx.commit().await.map_err(error)?;
+
+        self.loaded_summary = self.summary.clone();
+        self.dropped = 0;
+        Ok(())
+    }
+
+    fn trim(&mut self) {
+        match self.strategy {
+            Strategy::Window(interactions) => {
+                let users = self.history.iter().filter(|m| m.role == "user").count();
+                let mut drop = users.saturating_sub(interactions);
+                while drop > 0 && !self.history.is_empty() {
+                    self.history.remove(0);
+                    if self.history.first().is_none_or(|m| m.role == "user") {
+                        drop -= 1;
+                    }
+                }
+            }
+            Strategy::TokenBuffer(max_tokens) => {
+                while !self.history.is_empty() && estimate_tokens(&self.history) > max_tokens {
+                    self.history.remove(0);
+                }
+            }
+            Strategy::Summary { .. } => {}
+        }
+        // History always starts at a user message so no reply is left without its question.
+        while self.history.first().is_some_and(|m| m.role != "user") {
+            self.history.remove(0);
+        }
+    }
+}
+
+/// Rough token count (about four characters per token), good enough for budgeting history.
+fn estimate_tokens(messages: &[ChatMessage]) -> usize {
+    messages.iter().map(|m| m.content.chars().count().div_ceil(4) + 4).sum()
+}
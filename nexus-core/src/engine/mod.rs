@@ -1,13 +1,27 @@
+pub mod binary;
+pub mod cache;
+pub mod events;
 pub mod executor;
+pub mod images;
+pub mod knowledge;
+pub mod memory;
+pub mod structured;
+pub mod usage;
 
-use crate::models::{Node, Edge, Credential, McpServer, DataTableRow};
-use crate::clients::{OpenAiClient, OpenRouterClient};
-use crate::clients::openai::OpenAiMessage;
-use crate::clients::openrouter::{OpenRouterMessage, OpenRouterRequest};
-use crate::engine::executor::CodeExecutor;
+use crate::models::{Node, Edge, Credential, McpServer, DataTableRow, NodeExecutionResult, NodeRunDetails, CodeModule, CodeModuleRef, Workflow};
+use crate::clients::{registry, ChatMessage, ChatRequest, ChatResponse, ImageOptions, LlmProvider, OpenAiClient, ProviderSettings, ToolDefinition, TranscriptionOptions};
+use crate::clients::llm::ChatDelta;
+use crate::engine::cache::ResponseCache;
+use crate::engine::events::ExecutionEvent;
+use crate::engine::memory::AgentMemory;
+use crate::engine::structured::OutputSchema;
+use crate::engine::usage::UsageRecorder;
+use crate::engine::executor::{CodeError, CodeExecutor, CodeLimits, CodeOutput, ModuleSource};
 use sqlx::{Pool, Postgres};
 use uuid::Uuid;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::future::Future;
+use std::pin::Pin;
 
 fn interpolate_value(value: &str, input: &serde_json::Value) -> String {
     let mut result = value.to_string();
@@ -126,7 +140,8 @@ pub async fn execute_single_node(
     node: &Node, 
     all_nodes: &[Node], 
     edges: &[Edge],
-    input: &serde_json::Value
+    input: &serde_json::Value,
+    ctx: &ExecutionContext,
 ) -> Result<serde_json::Value, String> {
     match node.kind.as_str() {
         "http-request" => {
@@ -154,8 +169,19 @@ pub async fn execute_single_node(
             match req_builder.send().await {
                 Ok(response) => {
                     let status = response.status().as_u16();
-                    let body = response.text().await.map_err(|e| e.to_string())?;
-                    let body_json = serde_json::from_str::<serde_json::Value>(&body).unwrap_or(serde_json::json!(body));
+                    let body_json = if node.config.get("responseFormat").and_then(|v| v.as_str()) == Some("file") {
+                        // Binary bodies (images, PDFs, audio) in the same shape as uploaded files
+                        let mime_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok())
+                            .map(|v| v.split(';').next().unwrap_or(v).trim().to_string())
+                            .unwrap_or_else(|| "application/octet-stream".to_string());
+                        let file_name = response.url().path_segments().and_then(|mut s| s.next_back()).filter(|s| !s.is_empty())
+                            .unwrap_or("file").to_string();
+                        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+                        binary::file_output(&bytes, &mime_type, &file_name)
+                    } else {
+                        let body = response.text().await.map_err(|e| e.to_string())?;
+                        serde_json::from_str::<serde_json::Value>(&body).unwrap_or(serde_json::json!(body))
+                    };
                     let full_response = node.config.get("fullResponse").and_then(|v| v.as_bool()).unwrap_or(false);
                     if full_response { Ok(serde_json::json!({ "status_code": status, "body": body_json })) } else { Ok(body_json) }
                 }
@@ -163,10 +189,13 @@ pub async fn execute_single_node(
             }
         }
         "openai" => {
-            let api_key = get_api_key(pool, node, "openai", "OPENAI_API_KEY").await?;
-            let client = OpenAiClient::new(api_key);
+            let settings = registry().settings("openai", provider_settings(pool, node).await?)?;
+            let client = OpenAiClient::new(settings);
             let resource = node.config.get("resource").and_then(|v| v.as_str()).unwrap_or("chat");
-            let operation = node.config.get("operation").and_then(|v| v.as_str()).unwrap_or("completions");
+            let default_operation = match resource { "image" => "generate", "audio" => "transcribe", _ => "completions" };
+            let operation = node.config.get("operation").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or(default_operation);
+            let config_str = |key: &str| node.config.get(key).and_then(|v| v.as_str()).filter(|s| !s.is_empty()).map(|s| s.to_string());
+            let binary_property = config_str("binaryPropertyName").unwrap_or_else(|| "data".to_string());
             match (resource, operation) {
                 ("chat", "completions") => {
                     let model = node.config.get("model").and_then(|v| v.as_str()).unwrap_or("gpt-4o");
@@ -174,32 +203,67 @@ pub async fn execute_single_node(
                     let prompt = interpolate_value(prompt_raw, input);
                     let system = node.config.get("systemMessage").and_then(|v| v.as_str());
                     let mut messages = Vec::new();
-                    if let Some(s) = system { messages.push(OpenAiMessage { role: "system".to_string(), content: s.to_string(), tool_calls: None, tool_call_id: None }); }
-                    messages.push(OpenAiMessage { role: "user".to_string(), content: prompt.to_string(), tool_calls: None, tool_call_id: None });
-                    let result = client.generate(model, messages, None, None, None).await.map_err(|e| e.to_string())?;
-                    Ok(result)
+                    if let Some(s) = system { messages.push(ChatMessage::system(s)); }
+                    messages.push(ChatMessage::user(prompt).with_parts(images::image_parts(node, input)?));
+                    let request = ChatRequest { model: model.to_string(), messages, ..Default::default() };
+                    chat_output(pool, &client, request, node, ctx).await
                 },
-                ("image", "generate") => {
-                    let prompt_raw = node.config.get("prompt").and_then(|v| v.as_str()).ok_or("Prompt not specified")?;
-                    let prompt = interpolate_value(prompt_raw, input);
-                    let model = node.config.get("model").and_then(|v| v.as_str()).unwrap_or("dall-e-3");
-                    let size = node.config.get("size").and_then(|v| v.as_str()).unwrap_or("1024x1024");
-                    client.images_generate(&prompt, model, size, "standard", 1).await.map_err(|e| e.to_string())
+                ("image", "generate" | "edit" | "variation") => {
+                    let options = ImageOptions {
+                        model: config_str("model").unwrap_or_else(|| (if operation == "generate" { "dall-e-3" } else { "dall-e-2" }).to_string()),
+                        size: config_str("size"),
+                        quality: config_str("quality"),
+                        style: config_str("style"),
+                        response_format: config_str("responseFormat"),
+                        n: node.config.get("n").and_then(|v| v.as_u64()).filter(|n| *n > 0).map(|n| n as u32),
+                    };
+                    let prompt = || node.config.get("prompt").and_then(|v| v.as_str()).map(|p| interpolate_value(p, input)).ok_or("Prompt not specified");
+                    let response = match operation {
+                        "generate" => client.images_generate(&prompt()?, &options).await?,
+                        "edit" => {
+                            let image = binary::file_from_input(input, &binary_property, "image.png")?;
+                            let mask = config_str("maskPropertyName").map(|p| binary::file_from_input(input, &p, "mask.png")).transpose()?;
+                            client.images_edit(image, mask, &prompt()?, &options).await?
+                        }
+                        _ => client.images_variation(binary::file_from_input(input, &binary_property, "image.png")?, &options).await?,
+                    };
+                    Ok(image_output(response))
+                },
+                ("audio", "transcribe" | "translate") => {
+                    let file = binary::file_from_input(input, &binary_property, "audio.mp3")?;
+                    let options = TranscriptionOptions {
+                        model: config_str("model").unwrap_or_else(|| "whisper-1".to_string()),
+                        language: config_str("language"),
+                        prompt: config_str("prompt").map(|p| interpolate_value(&p, input)).filter(|p| !p.is_empty()),
+                        response_format: config_str("responseFormat"),
+                        temperature: node.config.get("temperature").and_then(|v| v.as_f64()).map(|v| v as f32),
+                    };
+                    client.audio_transcription(file, operation == "translate", &options).await
+                },
+                ("audio", "speech") => {
+                    let text = node.config.get("prompt").and_then(|v| v.as_str()).map(|p| interpolate_value(p, input)).ok_or("Text not specified")?;
+                    let model = config_str("model").unwrap_or_else(|| "tts-1".to_string());
+                    let voice = config_str("voice").unwrap_or_else(|| "alloy".to_string());
+                    let format = config_str("responseFormat");
+                    let speed = node.config.get("speed").and_then(|v| v.as_f64());
+                    let (audio, mime_type) = client.audio_speech(&model, &text, &voice, format.as_deref(), speed).await?;
+                    let file_name = config_str("fileName").map(|f| interpolate_value(&f, input))
+                        .unwrap_or_else(|| format!("speech.{}", format.as_deref().unwrap_or("mp3")));
+                    Ok(binary::file_output(&audio, &mime_type, &file_name))
                 },
                 _ => Err("Unsupported OpenAI operation".to_string())
             }
         }
         "openrouter" => {
-            let api_key = get_api_key(pool, node, "openrouter", "OPENROUTER_API_KEY").await?;
-            let client = OpenRouterClient::new(api_key);
+            let client = llm_provider(pool, node, "openrouter").await?;
             let model = node.config.get("model").and_then(|v| v.as_str()).unwrap_or("openai/gpt-4o-mini");
             let prompt_raw = node.config.get("prompt").and_then(|v| v.as_str()).ok_or("Prompt not specified")?;
             let prompt = interpolate_value(prompt_raw, input);
             let system = node.config.get("systemMessage").and_then(|v| v.as_str());
             let mut messages = Vec::new();
-            if let Some(s) = system { messages.push(OpenRouterMessage { role: "system".to_string(), content: s.to_string(), tool_calls: None, tool_call_id: None }); }
-            messages.push(OpenRouterMessage { role: "user".to_string(), content: prompt.to_string(), tool_calls: None, tool_call_id: None });
-            let request = OpenRouterRequest {
+            if let Some(s) = system { messages.push(ChatMessage::system(s)); }
+            messages.push(ChatMessage::user(prompt).with_parts(images::image_parts(node, input)?));
+            let request = ChatRequest {
                 model: model.to_string(),
                 messages,
                 temperature: node.config.get("temperature").and_then(|v| v.as_f64()).map(|v| v as f32),
@@ -207,45 +271,93 @@ pub async fn execute_single_node(
                 top_p: node.config.get("topP").and_then(|v| v.as_f64()).map(|v| v as f32),
                 frequency_penalty: node.config.get("frequencyPenalty").and_then(|v| v.as_f64()).map(|v| v as f32),
                 presence_penalty: node.config.get("presencePenalty").and_then(|v| v.as_f64()).map(|v| v as f32),
-                response_format: None,
-                tools: None,
-                tool_choice: None,
+                response_format: (node.config.get("responseFormat").and_then(|v| v.as_str()) == Some("json_object"))
+                    .then(|| serde_json::json!({ "type": "json_object" })),
+                ..Default::default()
             };
-            let result = client.generate(request).await.map_err(|e| e.to_string())?;
-            Ok(result)
+            chat_output(pool, client.as_ref(), request, node, ctx).await
         }
         "ai-agent" => {
-            execute_agent(pool, node, all_nodes, edges, input).await
+            execute_agent(pool, node, all_nodes, edges, input, ctx).await
         }
         "llm" => {
-            let api_key = get_api_key(pool, node, "openrouter", "OPENROUTER_API_KEY").await?;
-            let client = OpenRouterClient::new(api_key);
-            let model = node.config.get("model").and_then(|v| v.as_str()).unwrap_or("openai/gpt-4o-mini");
+            let provider = node.config.get("provider").and_then(|v| v.as_str()).unwrap_or("openrouter");
+            let client = llm_provider(pool, node, provider).await?;
+            let model = node.config.get("model").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or(default_model(provider));
             let prompt_raw = node.config.get("prompt").and_then(|v| v.as_str()).ok_or("Prompt not specified")?;
             let prompt = interpolate_value(prompt_raw, input);
-            let messages = vec![OpenRouterMessage { role: "user".to_string(), content: prompt, tool_calls: None, tool_call_id: None }];
-            let request = OpenRouterRequest {
+            let request = ChatRequest {
                 model: model.to_string(),
-                messages,
+                messages: vec![ChatMessage::user(prompt).with_parts(images::image_parts(node, input)?)],
                 temperature: Some(0.7),
                 max_tokens: Some(1000),
-                top_p: None,
-                frequency_penalty: None,
-                presence_penalty: None,
-                response_format: None,
-                tools: None,
-                tool_choice: None,
+                ..Default::default()
             };
-            let result = client.generate(request).await.map_err(|e| e.to_string())?;
-            Ok(result)
+            chat_output(pool, client.as_ref(), request, node, ctx).await
         }
         "tool" => Ok(node.config.clone()),
         "code" => {
             let code = node.config.get("code").and_then(|v| v.as_str()).unwrap_or("return $input;");
             let language = node.config.get("language").and_then(|v| v.as_str()).unwrap_or("javascript");
-            if language == "javascript" { CodeExecutor::execute_js(code, input).await.map_err(|e| format!("JS Error: {}", e)) }
-            else if language == "python" { CodeExecutor::execute_python(code, input).await.map_err(|e| format!("Python Error: {}", e)) }
-            else { Err(format!("Unsupported language: {}", language)) }
+            let mode = node.config.get("mode").and_then(|v| v.as_str()).unwrap_or("runOnceForAllItems");
+            let modules = load_code_modules(pool, language, &node.config).await?;
+
+            let mut details = NodeRunDetails::default();
+            let mut imported: Vec<String> = Vec::new();
+            let output = match mode {
+                "runOnceForAllItems" => {
+                    let output = match run_code(node, code, input, &modules, ctx).await {
+                        Ok(output) => output,
+                        Err(e) => {
+                            ctx.failures.record(node, NodeRunDetails { logs: e.logs, ..Default::default() });
+                            return Err(e.message);
+                        }
+                    };
+                    details.logs = output.logs;
+                    imported = output.modules;
+                    output.value
+                }
+                "runOnceForEachItem" => {
+                    let continue_on_fail = node.config.get("continueOnFail").and_then(|v| v.as_bool()).unwrap_or(false);
+                    let items = match input {
+                        serde_json::Value::Array(items) => items.clone(),
+                        item => vec![item.clone()],
+                    };
+
+                    let item_logs = |logs: Vec<serde_json::Value>, index: usize| logs.into_iter().map(move |mut log| {
+                        if let Some(obj) = log.as_object_mut() { obj.insert("itemIndex".to_string(), serde_json::json!(index)); }
+                        log
+                    });
+
+                    let mut outputs = Vec::new();
+                    for (index, item) in items.iter().enumerate() {
+                        match run_code(node, code, item, &modules, ctx).await {
+                            Ok(output) => {
+                                details.logs.extend(item_logs(output.logs, index));
+                                imported.extend(output.modules);
+                                if !output.value.is_null() { outputs.push(output.value); }
+                            }
+                            Err(e) if continue_on_fail => {
+                                details.logs.extend(item_logs(e.logs, index));
+                                outputs.push(serde_json::json!({ "error": e.message, "itemIndex": index }));
+                            }
+                            Err(e) => {
+                                details.logs.extend(item_logs(e.logs, index));
+                                ctx.failures.record(node, NodeRunDetails { logs: details.logs, ..Default::default() });
+                                return Err(format!("Item {}: {}", index, e.message));
+                            }
+                        }
+                    }
+                    serde_json::Value::Array(outputs)
+                }
+                _ => return Err(format!("Unsupported code mode: {}", mode)),
+            };
+
+            details.modules = modules.iter()
+                .filter(|m| imported.contains(&m.name))
+                .map(|m| CodeModuleRef { name: m.name.clone(), version: m.version })
+                .collect();
+            Ok(with_details(output, details))
         }
         "wait" => {
             let amount = node.config.get("amount").and_then(|v| v.as_f64()).unwrap_or(1.0);
@@ -309,22 +421,10 @@ pub async fn execute_single_node(
             let operation = node.config.get("operation").and_then(|v| v.as_str()).unwrap_or("select");
             let cred_id = node.config.get("credentialId").and_then(|v| v.as_str()).ok_or("Credential not specified")?;
             
-            // 1. Fetch Credentials
-            let cred = sqlx::query_as::<_, Credential>("SELECT * FROM credentials WHERE id = $1")
-                .bind(Uuid::parse_str(cred_id).map_err(|e| e.to_string())?)
-                .fetch_optional(pool)
-                .await
-                .map_err(|e| e.to_string())?
-                .ok_or("Credential not found")?;
+            // 1. Resolve Credentials
+            let connection_url = postgres_credential_url(pool, cred_id).await?;
 
             // 2. Build Connection Pool for External DB
-            let host = cred.data.get("host").and_then(|v| v.as_str()).unwrap_or("localhost");
-            let port = cred.data.get("port").and_then(|v| v.as_u64()).or_else(|| cred.data.get("port").and_then(|v| v.as_str()).and_then(|s| s.parse::<u64>().ok())).unwrap_or(5432);
-            let user = cred.data.get("user").and_then(|v| v.as_str()).unwrap_or("postgres");
-            let password = cred.data.get("password").and_then(|v| v.as_str()).unwrap_or("");
-            let database = cred.data.get("database").and_then(|v| v.as_str()).unwrap_or("postgres");
-            
-            let connection_url = format!("postgres://{}:{}@{}:{}/{}", user, password, host, port, database);
             let ext_pool = sqlx::postgres::PgPoolOptions::new()
                 .max_connections(1)
                 .connect(&connection_url)
@@ -342,23 +442,7 @@ pub async fn execute_single_node(
                         .await
                         .map_err(|e: sqlx::Error| e.to_string())?;
                     
-                    let mut results = Vec::new();
-                    for row in rows {
-                        use sqlx::Column;
-                        use sqlx::Row;
-                        let mut map = serde_json::Map::new();
-                        for col in row.columns() {
-                            let name = col.name();
-                            let val: serde_json::Value = if let Ok(v) = row.try_get::<String, _>(name) { serde_json::json!(v) }
-                            else if let Ok(v) = row.try_get::<i64, _>(name) { serde_json::json!(v) }
-                            else if let Ok(v) = row.try_get::<f64, _>(name) { serde_json::json!(v) }
-                            else if let Ok(v) = row.try_get::<bool, _>(name) { serde_json::json!(v) }
-                            else { serde_json::json!(null) };
-                            map.insert(name.to_string(), val);
-                        }
-                        results.push(serde_json::Value::Object(map));
-                    }
-                    serde_json::Value::Array(results)
+                    serde_json::Value::Array(rows.iter().map(row_to_json).collect())
                 },
                 "select" => {
                     let schema = node.config.get("schema").and_then(|v| v.as_str()).unwrap_or("public");
@@ -377,23 +461,7 @@ pub async fn execute_single_node(
                     query = format!("{} LIMIT {}", query, limit);
 
                     let rows = sqlx::query(&query).fetch_all(&ext_pool).await.map_err(|e: sqlx::Error| e.to_string())?;
-                    let mut results = Vec::new();
-                    for row in rows {
-                        use sqlx::Column;
-                        use sqlx::Row;
-                        let mut map = serde_json::Map::new();
-                        for col in row.columns() {
-                            let name = col.name();
-                            let val: serde_json::Value = if let Ok(v) = row.try_get::<String, _>(name) { serde_json::json!(v) }
-                            else if let Ok(v) = row.try_get::<i64, _>(name) { serde_json::json!(v) }
-                            else if let Ok(v) = row.try_get::<f64, _>(name) { serde_json::json!(v) }
-                            else if let Ok(v) = row.try_get::<bool, _>(name) { serde_json::json!(v) }
-                            else { serde_json::json!(null) };
-                            map.insert(name.to_string(), val);
-                        }
-                        results.push(serde_json::Value::Object(map));
-                    }
-                    serde_json::Value::Array(results)
+                    serde_json::Value::Array(rows.iter().map(row_to_json).collect())
                 },
                 "insert" => {
                     let schema = node.config.get("schema").and_then(|v| v.as_str()).unwrap_or("public");
@@ -424,17 +492,7 @@ pub async fn execute_single_node(
                     }
                     
                     let row = q.fetch_one(&ext_pool).await.map_err(|e: sqlx::Error| e.to_string())?;
-                    use sqlx::Column;
-                    use sqlx::Row;
-                    let mut map = serde_json::Map::new();
-                    for col in row.columns() {
-                        let name = col.name();
-                        let val: serde_json::Value = if let Ok(v) = row.try_get::<String, _>(name) { serde_json::json!(v) }
-                        else if let Ok(v) = row.try_get::<i64, _>(name) { serde_json::json!(v) }
-                        else { serde_json::json!(null) };
-                        map.insert(name.to_string(), val);
-                    }
-                    serde_json::Value::Object(map)
+                    row_to_json(&row)
                 },
                 _ => serde_json::json!({ "status": "unsupported operation" })
             };
@@ -632,7 +690,81 @@ pub async fn execute_single_node(
                 _ => Err(format!("Unsupported data-table operation: {}", operation))
             }
         },
-        "trigger-start" | "trigger-schedule" | "trigger-webhook" => Ok(serde_json::json!({ "triggered": true })),
+        "execute-workflow" => {
+            let workflow_id = node.config.get("workflowId").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).ok_or("Workflow not specified")?;
+            let workflow_id = Uuid::parse_str(workflow_id).map_err(|e| format!("Invalid workflow id: {}", e))?;
+            if workflow_id.to_string() == ctx.workflow_id {
+                return Err("A workflow cannot execute itself".to_string());
+            }
+            if ctx.call_stack.contains(&workflow_id) {
+                return Err(format!("Workflow {} is already running further up this call chain", workflow_id));
+            }
+            let mut call_stack = ctx.call_stack.clone();
+            call_stack.extend(Uuid::parse_str(&ctx.workflow_id).ok());
+            run_sub_workflow(pool.clone(), workflow_id, input.clone(), call_stack).await
+        },
+        "knowledge-retrieval" => {
+            let kb_id = node.config.get("knowledgeBaseId").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).ok_or("Knowledge base not specified")?;
+            let kb_id = Uuid::parse_str(kb_id).map_err(|e| format!("Invalid knowledge base id: {}", e))?;
+            let query = interpolate_value(node.config.get("query").and_then(|v| v.as_str()).unwrap_or("{{ $input.query }}"), input);
+            if query.trim().is_empty() {
+                return Err("Query is empty".to_string());
+            }
+            let top_k = node.config.get("topK").and_then(|v| v.as_i64()).filter(|k| *k > 0).unwrap_or(knowledge::DEFAULT_TOP_K);
+            let kb = knowledge::load(pool, kb_id).await?;
+            let (mut results, usage) = knowledge::search(pool, &kb, &query, top_k).await?;
+            if let Some(usage) = &usage {
+                ctx.usage.record(node, &kb.embedding_provider, &kb.embedding_model, usage);
+            }
+            if let Some(min_score) = node.config.get("minScore").and_then(|v| v.as_f64()) {
+                results.retain(|r| r.score >= min_score);
+            }
+            Ok(serde_json::json!({ "query": query, "results": results }))
+        },
+        "embeddings" => {
+            let provider = node.config.get("provider").and_then(|v| v.as_str()).unwrap_or("openai");
+            let model = node.config.get("model").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or("text-embedding-3-small");
+            let field = node.config.get("field").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or("text");
+            let output_field = node.config.get("outputField").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or("embedding");
+            let dimensions = node.config.get("dimensions").and_then(|v| v.as_u64()).filter(|d| *d > 0).map(|d| d as u32);
+
+            // Embeds `field` of every item (or of the single input object) and adds the vector as `outputField`
+            let (mut items, single) = match input {
+                serde_json::Value::Array(items) => (items.clone(), false),
+                other => (vec![other.clone()], true),
+            };
+            let texts = items.iter().enumerate().map(|(i, item)| {
+                let value = match item {
+                    serde_json::Value::String(_) => Some(item),
+                    _ => field.split('.').try_fold(item, |v, key| v.get(key)),
+                };
+                match value {
+                    Some(serde_json::Value::String(s)) if !s.is_empty() => Ok(s.clone()),
+                    Some(serde_json::Value::Null) | Some(serde_json::Value::String(_)) | None => Err(format!("Item {} has no text in field '{}'", i, field)),
+                    Some(other) => Ok(other.to_string()),
+                }
+            }).collect::<Result<Vec<_>, _>>()?;
+            if texts.is_empty() {
+                return Ok(serde_json::json!([]));
+            }
+
+            let client = llm_provider(pool, node, provider).await?;
+            let embeddings = client.embeddings(model, texts, dimensions).await?;
+            if let Some(usage) = &embeddings.usage {
+                ctx.usage.record(node, client.name(), model, usage);
+            }
+            for (item, vector) in items.iter_mut().zip(embeddings.vectors) {
+                match item {
+                    serde_json::Value::Object(map) => { map.insert(output_field.to_string(), serde_json::json!(vector)); }
+                    other => *other = serde_json::json!({ field: other.clone(), output_field: vector }),
+                }
+            }
+            if single { Ok(items.remove(0)) } else { Ok(serde_json::Value::Array(items)) }
+        },
+        "trigger-start" | "trigger-schedule" | "trigger-webhook" => {
+            // Registered triggers hand over their event (request, tick) as input; manual runs start empty
+            if input.as_object().is_some_and(|o| !o.is_empty()) { Ok(input.clone()) } else { Ok(serde_json::json!({ "triggered": true })) }
+        },
         "rss-feed-read" => {
             let url_raw = node.config.get("url").and_then(|v| v.as_str()).ok_or("URL not specified")?;
             let url = interpolate_value(url_raw, input);
@@ -1151,9 +1283,13 @@ pub async fn execute_single_node(
                 _ => Err(format!("Unsupported action: {}", action))
             }
         }
+        "postgres-trigger" | "local-file-trigger" | "error-trigger" | "form-trigger" => Ok(input.clone()),
         "chat-trigger" => {
             if let Some(val) = node.config.get("initialInput") {
                 Ok(val.clone())
+            } else if input.as_object().is_some_and(|o| !o.is_empty()) {
+                // Started through the chat API, which passes the message as the trigger input
+                Ok(input.clone())
             } else {
                 Ok(serde_json::json!({ "triggered": true }))
             }
@@ -1163,32 +1299,565 @@ pub async fn execute_single_node(
 }
 
 async fn get_api_key(pool: &Pool<Postgres>, node: &Node, _provider: &str, env_var: &str) -> Result<String, String> {
-    if let Some(cred_id) = node.config.get("credentialId").and_then(|v| v.as_str()) {
-        if let Ok(id) = Uuid::parse_str(cred_id) {
-            let cred = sqlx::query_as::<_, Credential>("SELECT * FROM credentials WHERE id = $1").bind(id).fetch_optional(pool).await.map_err(|e| e.to_string())?;
-            if let Some(c) = cred { return c.data.get("api_key").and_then(|v| v.as_str()).map(|s| s.to_string()).ok_or("API key not found in credential".to_string()); }
-        }
+    if let Some(c) = node_credential(pool, node).await? {
+        return c.data.get("api_key").and_then(|v| v.as_str()).map(|s| s.to_string()).ok_or("API key not found in credential".to_string());
     }
     std::env::var(env_var).map_err(|_| format!("{} not set", env_var))
 }
 
-async fn execute_agent(pool: &Pool<Postgres>, node: &Node, all_nodes: &[Node], edges: &[Edge], input: &serde_json::Value) -> Result<serde_json::Value, String> {
+async fn node_credential(pool: &Pool<Postgres>, node: &Node) -> Result<Option<Credential>, String> {
+    let Some(id) = node.config.get("credentialId").and_then(|v| v.as_str()).and_then(|s| Uuid::parse_str(s).ok()) else { return Ok(None); };
+    sqlx::query_as::<_, Credential>("SELECT * FROM credentials WHERE id = $1").bind(id).fetch_optional(pool).await.map_err(|e| e.to_string())
+}
+
+/// API key and base URL from the node's credential. Anything the credential leaves out comes from the environment.
+async fn provider_settings(pool: &Pool<Postgres>, node: &Node) -> Result<ProviderSettings, String> {
+    Ok(node_credential(pool, node).await?.map(|c| ProviderSettings::from_credential(&c.data)).unwrap_or_default())
+}
+
+/// Sends a chat request, streaming tokens and tool-call deltas to the execution's event stream while
+/// someone is watching it. Set `stream: false` on the node to always wait for the whole completion.
+/// Nodes with `cache: true` are answered from the response cache when they sent the same request before.
+/// With a `schema`, replies that don't match it aren't cached, so a repaired request starts from a fresh answer next time.
+async fn stream_chat(pool: &Pool<Postgres>, client: &dyn LlmProvider, request: ChatRequest, schema: Option<&OutputSchema>, node: &Node, ctx: &ExecutionContext) -> Result<ChatResponse, String> {
+    let model = request.model.clone();
+    let cache = ResponseCache::from_node(node).map(|cache| (ResponseCache::key(client.name(), client.base_url(), &request), cache));
+    if let Some((key, cache)) = &cache
+        && let Some(response) = cache.get(pool, key).await {
+        ctx.usage.record_cache_hit(node, client.name(), &model);
+        if !response.message.content.is_empty() {
+            events::publish(ctx.execution_id, ExecutionEvent::Token { node_id: node.id.to_string(), text: response.message.content.clone() });
+        }
+        return Ok(response);
+    }
+
+    let response = send_chat(client, request, node, ctx).await?;
+    if let Some(usage) = &response.usage {
+        ctx.usage.record(node, client.name(), &model, usage);
+    }
+    if let Some((key, cache)) = &cache
+        && schema.is_none_or(|s| !response.message.tool_calls.is_empty() || s.parse(&response.message.content).is_ok()) {
+        cache.put(pool, key, &response).await;
+    }
+    Ok(response)
+}
+
+async fn send_chat(client: &dyn LlmProvider, request: ChatRequest, node: &Node, ctx: &ExecutionContext) -> Result<ChatResponse, String> {
+    let enabled = node.config.get("stream").and_then(|v| v.as_bool()).unwrap_or(true);
+    if !enabled || !events::is_watched(ctx.execution_id) {
+        return client.chat(request).await;
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let node_id = node.id.to_string();
+    let forward = async {
+        while let Some(delta) = rx.recv().await {
+            let event = match delta {
+                ChatDelta::Text(text) => ExecutionEvent::Token { node_id: node_id.clone(), text },
+                ChatDelta::ToolCall { index, id, name, arguments } => ExecutionEvent::ToolCallDelta { node_id: node_id.clone(), index, id, name, arguments },
+            };
+            events::publish(ctx.execution_id, event);
+        }
+    };
+    let (response, _) = tokio::join!(client.chat_stream(request, tx), forward);
+    response
+}
+
+/// Output of an LLM node: the raw API response, or the parsed reply when the node has an output schema.
+async fn chat_output(pool: &Pool<Postgres>, client: &dyn LlmProvider, request: ChatRequest, node: &Node, ctx: &ExecutionContext) -> Result<serde_json::Value, String> {
+    match OutputSchema::from_node(node)? {
+        Some(schema) => structured_chat(pool, client, request, &schema, node, ctx).await,
+        None => Ok(stream_chat(pool, client, request, None, node, ctx).await?.raw),
+    }
+}
+
+/// Asks for JSON following `schema` and re-prompts with the validation errors until the reply matches
+/// or the schema's retries run out.
+async fn structured_chat(pool: &Pool<Postgres>, client: &dyn LlmProvider, mut request: ChatRequest, schema: &OutputSchema, node: &Node, ctx: &ExecutionContext) -> Result<serde_json::Value, String> {
+    request.response_format = Some(schema.response_format());
+    let mut last_errors = String::new();
+    for _ in 0..=schema.retries {
+        let message = stream_chat(pool, client, request.clone(), Some(schema), node, ctx).await?.message;
+        match schema.parse(&message.content) {
+            Ok(value) => return Ok(value),
+            Err(errors) => {
+                request.messages.push(ChatMessage::assistant(message.content));
+                request.messages.push(ChatMessage::user(schema.repair_prompt(&errors)));
+                last_errors = errors;
+            }
+        }
+    }
+    Err(format!("Reply did not match the output schema after {} attempts:\n{}", schema.retries + 1, last_errors))
+}
+
+/// Model used by the `llm` and `ai-agent` nodes when none is configured.
+fn default_model(provider: &str) -> &'static str {
+    match provider {
+        "anthropic" => "claude-sonnet-4-5",
+        "openrouter" => "openai/gpt-4o-mini",
+        _ => "gpt-4o",
+    }
+}
+
+/// Builds the registered LLM provider `provider` for the node.
+async fn llm_provider(pool: &Pool<Postgres>, node: &Node, provider: &str) -> Result<std::sync::Arc<dyn LlmProvider>, String> {
+    registry().create(provider, provider_settings(pool, node).await?)
+}
+
+/// Runs the script of a Code node once over `input` in the configured language.
+async fn run_code(node: &Node, code: &str, input: &serde_json::Value, modules: &[ModuleSource], ctx: &ExecutionContext) -> Result<CodeOutput, CodeError> {
+    let language = node.config.get("language").and_then(|v| v.as_str()).unwrap_or("javascript");
+    match language {
+        "javascript" => CodeExecutor::execute_js(code, input, &ctx.code_context(), modules, CodeLimits::from_config(&node.config)).await.map_err(|e| e.context("JS Error")),
+        "python" => {
+            let use_virtualenv = node.config.get("useVirtualenv").and_then(|v| v.as_bool()).unwrap_or(false);
+            let requirements = node.config.get("requirements").and_then(|v| v.as_str()).unwrap_or("");
+            let interpreter = CodeExecutor::python_interpreter(&ctx.workflow_id, use_virtualenv, requirements).await?;
+            CodeExecutor::execute_python(code, input, modules, CodeLimits::python_from_config(&node.config), &interpreter).await.map_err(|e| e.context("Python Error"))
+        }
+        "wasm" => {
+            let module = CodeExecutor::load_wasm_module(&node.config).await?;
+            CodeExecutor::execute_wasm(module, input, CodeLimits::from_config(&node.config)).await.map_err(|e| format!("WASM Error: {}", e).into())
+        }
+        _ => Err(format!("Unsupported language: {}", language).into()),
+    }
+}
+
+/// Loads the shared code modules a Code node may import: the latest version of every module in its
+/// language, or the version pinned in the node's `moduleVersions` (`{ "name": version }`).
+async fn load_code_modules(pool: &Pool<Postgres>, language: &str, config: &serde_json::Value) -> Result<Vec<ModuleSource>, String> {
+    if language == "wasm" {
+        return Ok(Vec::new());
+    }
+
+    let modules = sqlx::query_as::<_, CodeModule>("SELECT * FROM code_modules WHERE language = $1")
+        .bind(language)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to load code modules: {}", e))?;
+
+    let pins = config.get("moduleVersions").and_then(|v| v.as_object());
+    if let Some(pins) = pins
+        && let Some(missing) = pins.keys().find(|name| !modules.iter().any(|m| &m.name == *name))
+    {
+        return Err(format!("Code module '{}' not found", missing));
+    }
+
+    let mut sources = Vec::new();
+    for module in modules {
+        let pinned = pins.and_then(|p| p.get(&module.name)).and_then(|v| v.as_i64()).map(|v| v as i32);
+        match pinned {
+            Some(version) if version != module.version => {
+                let code = sqlx::query_scalar::<_, String>("SELECT code FROM code_module_versions WHERE module_id = $1 AND version = $2")
+                    .bind(module.id)
+                    .bind(version)
+                    .fetch_optional(pool)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .ok_or(format!("Code module '{}' has no version {}", module.name, version))?;
+                sources.push(ModuleSource { name: module.name, version, code });
+            }
+            _ => sources.push(ModuleSource { name: module.name, version: module.version, code: module.code }),
+        }
+    }
+    Ok(sources)
+}
+
+/// Details about the running execution that nodes can read besides their own input.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionContext {
+    pub execution_id: Uuid,
+    pub workflow_id: String,
+    pub workflow_name: String,
+    /// Latest output of every node that already ran, keyed by node label.
+    pub node_outputs: serde_json::Map<String, serde_json::Value>,
+    /// LLM calls of the running nodes, collected into their results by [`usage::finish_node`].
+    pub usage: UsageRecorder,
+    /// Workflows waiting on this one through `execute-workflow` nodes, outermost first.
+    pub call_stack: Vec<Uuid>,
+    /// What nodes reported before failing, collected into their failed results by [`failure_details`].
+    pub failures: FailureRecorder,
+}
+
+/// Side data of nodes that failed, such as console output or an agent's turns. A node's `Err` only
+/// carries a message, so the node records the rest here.
+#[derive(Debug, Clone, Default)]
+pub struct FailureRecorder(std::sync::Arc<std::sync::Mutex<std::collections::HashMap<Uuid, NodeRunDetails>>>);
+
+impl FailureRecorder {
+    pub fn record(&self, node: &Node, details: NodeRunDetails) {
+        self.0.lock().unwrap().insert(node.id, details);
+    }
+
+    fn take(&self, node_id: Uuid) -> NodeRunDetails {
+        self.0.lock().unwrap().remove(&node_id).unwrap_or_default()
+    }
+}
+
+/// Details for the result of a node that failed: what it recorded before failing and its LLM usage.
+pub async fn failure_details(pool: &Pool<Postgres>, ctx: &ExecutionContext, node: &Node) -> NodeRunDetails {
+    NodeRunDetails { usage: usage::finish_node(pool, ctx, node).await, ..ctx.failures.take(node.id) }
+}
+
+impl ExecutionContext {
+    pub fn new(execution_id: Uuid, workflow_id: impl Into<String>, workflow_name: impl Into<String>) -> Self {
+        Self { execution_id, workflow_id: workflow_id.into(), workflow_name: workflow_name.into(), node_outputs: serde_json::Map::new(), usage: UsageRecorder::default(), call_stack: Vec::new(), failures: FailureRecorder::default() }
+    }
+
+    /// Rebuilds the context of a resumed execution from the results it already recorded.
+    pub fn from_results(execution_id: Uuid, workflow_id: impl Into<String>, workflow_name: impl Into<String>, results: &[NodeExecutionResult]) -> Self {
+        let mut ctx = Self::new(execution_id, workflow_id, workflow_name);
+        for result in results {
+            if let Some(output) = &result.output {
+                ctx.node_outputs.insert(result.node_name.clone(), output.clone());
+            }
+        }
+        ctx
+    }
+
+    pub fn record(&mut self, node: &Node, output: &serde_json::Value) {
+        self.node_outputs.insert(node.label.clone(), output.clone());
+    }
+
+    /// The `$execution`, `$workflow` and `$node` data handed to Code nodes.
+    fn code_context(&self) -> serde_json::Value {
+        serde_json::json!({
+            "execution": { "id": self.execution_id.to_string() },
+            "workflow": { "id": self.workflow_id, "name": self.workflow_name },
+            "nodes": self.node_outputs,
+        })
+    }
+}
+
+/// Node outputs that carry side data such as captured console output are wrapped as `{ "__output", "__details" }`.
+/// Runners unwrap them with [`take_details`] so the details land on `NodeExecutionResult` instead of the data.
+fn with_details(output: serde_json::Value, details: NodeRunDetails) -> serde_json::Value {
+    if details.is_empty() { return output; }
+    serde_json::json!({ "__output": output, "__details": details })
+}
+
+/// Splits the side data off a node output produced by [`with_details`].
+pub fn take_details(output: serde_json::Value) -> (serde_json::Value, NodeRunDetails) {
+    match output {
+        serde_json::Value::Object(mut obj) if obj.contains_key("__details") && obj.contains_key("__output") => {
+            let details = obj.remove("__details").and_then(|v| serde_json::from_value(v).ok()).unwrap_or_default();
+            (obj.remove("__output").unwrap_or_default(), details)
+        }
+        other => (other, NodeRunDetails::default()),
+    }
+}
+
+/// Builds a connection URL for an external database from a `postgres` credential.
+pub async fn postgres_credential_url(pool: &Pool<Postgres>, cred_id: &str) -> Result<String, String> {
+    let cred = sqlx::query_as::<_, Credential>("SELECT * FROM credentials WHERE id = $1")
+        .bind(Uuid::parse_str(cred_id).map_err(|e| e.to_string())?)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Credential not found")?;
+
+    let host = cred.data.get("host").and_then(|v| v.as_str()).unwrap_or("localhost");
+    let port = cred.data.get("port").and_then(|v| v.as_u64()).or_else(|| cred.data.get("port").and_then(|v| v.as_str()).and_then(|s| s.parse::<u64>().ok())).unwrap_or(5432);
+    let user = cred.data.get("user").and_then(|v| v.as_str()).unwrap_or("postgres");
+    let password = cred.data.get("password").and_then(|v| v.as_str()).unwrap_or("");
+    let database = cred.data.get("database").and_then(|v| v.as_str()).unwrap_or("postgres");
+
+    Ok(format!("postgres://{}:{}@{}:{}/{}", user, password, host, port, database))
+}
+
+/// Converts a row from an external database into a JSON object keyed by column name.
+pub fn row_to_json(row: &sqlx::postgres::PgRow) -> serde_json::Value {
+    use sqlx::Column;
+    use sqlx::Row;
+    let mut map = serde_json::Map::new();
+    for col in row.columns() {
+        let name = col.name();
+        let val: serde_json::Value = if let Ok(v) = row.try_get::<String, _>(name) { serde_json::json!(v) }
+        else if let Ok(v) = row.try_get::<i64, _>(name) { serde_json::json!(v) }
+        else if let Ok(v) = row.try_get::<i32, _>(name) { serde_json::json!(v) }
+        else if let Ok(v) = row.try_get::<f64, _>(name) { serde_json::json!(v) }
+        else if let Ok(v) = row.try_get::<bool, _>(name) { serde_json::json!(v) }
+        else if let Ok(v) = row.try_get::<Uuid, _>(name) { serde_json::json!(v) }
+        else if let Ok(v) = row.try_get::<chrono::DateTime<chrono::Utc>, _>(name) { serde_json::json!(v) }
+        else if let Ok(v) = row.try_get::<serde_json::Value, _>(name) { v }
+        else { serde_json::json!(null) };
+        map.insert(name.to_string(), val);
+    }
+    serde_json::Value::Object(map)
+}
+
+/// Runs a stored workflow starting at `start_node`, records it in `executions` and returns its node results.
+/// Used by event-driven triggers that start executions outside of an HTTP request.
+pub async fn run_workflow_from_node(
+    pool: &Pool<Postgres>,
+    workflow_id: Uuid,
+    workflow_name: &str,
+    nodes: &[Node],
+    edges: &[Edge],
+    start_node: &Node,
+    input: serde_json::Value,
+) -> (Uuid, Vec<NodeExecutionResult>) {
+    let execution_id = Uuid::new_v4();
+    let results = run_workflow_execution(pool, execution_id, workflow_id, workflow_name, nodes, edges, start_node, input).await;
+    (execution_id, results)
+}
+
+/// Same as [`run_workflow_from_node`] under a caller-chosen execution id, so clients can subscribe
+/// to its events before it starts.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_workflow_execution(
+    pool: &Pool<Postgres>,
+    execution_id: Uuid,
+    workflow_id: Uuid,
+    workflow_name: &str,
+    nodes: &[Node],
+    edges: &[Edge],
+    start_node: &Node,
+    input: serde_json::Value,
+) -> Vec<NodeExecutionResult> {
+    let ctx = ExecutionContext::new(execution_id, workflow_id.to_string(), workflow_name);
+    run_with_context(pool, ctx, workflow_id, nodes, edges, start_node, input).await
+}
+
+async fn run_with_context(
+    pool: &Pool<Postgres>,
+    mut ctx: ExecutionContext,
+    workflow_id: Uuid,
+    nodes: &[Node],
+    edges: &[Edge],
+    start_node: &Node,
+    input: serde_json::Value,
+) -> Vec<NodeExecutionResult> {
+    let execution_id = ctx.execution_id;
+    let workflow_name = ctx.workflow_name.clone();
+    let workflow_name = workflow_name.as_str();
+    let start_time = chrono::Utc::now();
+    let _ = sqlx::query("INSERT INTO executions (id, workflow_id, workflow_name, status, results, start_time) VALUES ($1, $2, $3, $4, $5, $6)")
+        .bind(execution_id)
+        .bind(workflow_id.to_string())
+        .bind(workflow_name)
+        .bind("running")
+        .bind(serde_json::json!([]))
+        .bind(start_time)
+        .execute(pool)
+        .await;
+
+    let mut results = Vec::new();
+    let mut failure: Option<(String, String)> = None;
+    let mut queue = std::collections::VecDeque::new();
+    queue.push_back((start_node, input));
+
+    while let Some((current_node, current_input)) = queue.pop_front() {
+        let node_start_time = std::time::Instant::now();
+        events::publish(execution_id, ExecutionEvent::NodeStarted { node_id: current_node.id.to_string(), node_name: current_node.label.clone() });
+        match execute_single_node(pool, current_node, nodes, edges, &current_input, &ctx).await {
+            Ok(output) => {
+                let (output, mut details) = take_details(output);
+                details.usage = usage::finish_node(pool, &ctx, current_node).await;
+                ctx.record(current_node, &output);
+                let filtered = output.get("__filtered").and_then(|v| v.as_bool()).unwrap_or(false);
+                let port = output.get("__port").and_then(|v| v.as_str()).map(|s| s.to_string());
+                results.push(NodeExecutionResult {
+                    node_id: current_node.id.to_string(),
+                    node_name: current_node.label.clone(),
+                    success: true,
+                    output: Some(output.clone()),
+                    error: None,
+                    execution_time_ms: node_start_time.elapsed().as_millis() as u64,
+                    details,
+                });
+                events::publish(execution_id, ExecutionEvent::NodeFinished { result: results[results.len() - 1].clone() });
+                if filtered { continue; }
+
+                for edge in edges.iter().filter(|e| e.from == current_node.id && !is_sub_node_edge(e) && (port.is_none() || e.from_port == port)) {
+                    if let Some(next_node) = nodes.iter().find(|n| n.id == edge.to) {
+                        queue.push_back((next_node, output.clone()));
+                    }
+                }
+            }
+            Err(e) => {
+                failure = Some((current_node.label.clone(), e.clone()));
+                results.push(NodeExecutionResult {
+                    node_id: current_node.id.to_string(),
+                    node_name: current_node.label.clone(),
+                    success: false,
+                    output: None,
+                    error: Some(e),
+                    execution_time_ms: node_start_time.elapsed().as_millis() as u64,
+                    details: failure_details(pool, &ctx, current_node).await,
+                });
+                events::publish(execution_id, ExecutionEvent::NodeFinished { result: results[results.len() - 1].clone() });
+                break;
+            }
+        }
+    }
+
+    let status = if failure.is_none() { "success" } else { "failed" };
+    let _ = sqlx::query("UPDATE executions SET status = $1, results = $2, end_time = NOW() WHERE id = $3")
+        .bind(status)
+        .bind(serde_json::to_value(&results).unwrap_or_default())
+        .bind(execution_id)
+        .execute(pool)
+        .await;
+    usage::update_execution(pool, execution_id).await;
+    events::finish(execution_id, status, &results);
+
+    if let Some((last_node, error)) = failure {
+        let chain = error_chain(nodes, &results);
+        tokio::spawn(notify_error_workflow(pool.clone(), workflow_id, workflow_name.to_string(), execution_id, last_node, error, chain));
+    }
+
+    results
+}
+
+/// Runs another stored workflow from its Manual Trigger with `input` and returns the output of its last node.
+/// `call_stack` holds the calling workflows, so a workflow further up the chain can't be entered again.
+/// Boxed because the sub-workflow's nodes run through `execute_single_node` again.
+fn run_sub_workflow(pool: Pool<Postgres>, workflow_id: Uuid, input: serde_json::Value, call_stack: Vec<Uuid>) -> Pin<Box<dyn Future<Output = Result<serde_json::Value, String>> + Send>> {
+    Box::pin(async move {
+        let workflow = sqlx::query_as::<_, Workflow>("SELECT * FROM workflows WHERE id = $1")
+            .bind(workflow_id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or(format!("Workflow not found: {}", workflow_id))?;
+
+        let nodes: Vec<Node> = serde_json::from_value(workflow.nodes.clone()).unwrap_or_default();
+        let edges: Vec<Edge> = serde_json::from_value(workflow.edges.clone()).unwrap_or_default();
+        let start = nodes.iter().find(|n| n.kind == "trigger-start")
+            .ok_or(format!("Workflow '{}' has no Manual Trigger node", workflow.name))?;
+
+        let mut ctx = ExecutionContext::new(Uuid::new_v4(), workflow.id.to_string(), workflow.name.clone());
+        ctx.call_stack = call_stack;
+        let results = run_with_context(&pool, ctx, workflow.id, &nodes, &edges, start, input).await;
+        match results.last() {
+            Some(result) if !result.success => Err(format!("Workflow '{}' failed at '{}': {}", workflow.name, result.node_name, result.error.clone().unwrap_or_default())),
+            Some(result) => Ok(result.output.clone().unwrap_or_default()),
+            None => Ok(serde_json::Value::Null),
+        }
+    })
+}
+
+/// Workflows whose failures led to an execution, read from the `errorChain` its error trigger received.
+/// Empty for executions that didn't start from an `error-trigger`.
+pub fn error_chain(nodes: &[Node], results: &[NodeExecutionResult]) -> Vec<Uuid> {
+    results.iter()
+        .find(|r| nodes.iter().any(|n| n.kind == "error-trigger" && n.id.to_string() == r.node_id))
+        .and_then(|r| r.output.as_ref()?.get("errorChain")?.as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|v| v.as_str().and_then(|s| Uuid::parse_str(s).ok()))
+        .collect()
+}
+
+/// Starts the `errorWorkflowId` configured on a workflow after one of its executions failed.
+/// The error workflow is entered through its `error-trigger` node and doesn't need to be active.
+/// `chain` lists the workflows whose failures led to this one (see [`error_chain`]); it is passed on
+/// as `errorChain` so error workflows that fail each other stop once the chain comes back around.
+/// Boxed because the error workflow itself runs through `run_workflow_from_node`.
+pub fn notify_error_workflow(
+    pool: Pool<Postgres>,
+    workflow_id: Uuid,
+    workflow_name: String,
+    execution_id: Uuid,
+    last_node: String,
+    error: String,
+    chain: Vec<Uuid>,
+) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    Box::pin(async move {
+        let Ok(Some(settings)) = sqlx::query_scalar::<_, serde_json::Value>("SELECT settings FROM workflows WHERE id = $1")
+            .bind(workflow_id)
+            .fetch_optional(&pool)
+            .await else { return; };
+        let Some(error_workflow_id) = settings.get("errorWorkflowId").and_then(|v| v.as_str()).and_then(|s| Uuid::parse_str(s).ok()) else { return; };
+        // A workflow handling its own failures would keep re-triggering itself
+        if error_workflow_id == workflow_id { return; }
+        if chain.contains(&error_workflow_id) {
+            eprintln!("Error workflow {} for '{}' already failed earlier in this chain, not starting it again", error_workflow_id, workflow_name);
+            return;
+        }
+        let mut chain = chain;
+        chain.push(workflow_id);
+
+        let Ok(Some(error_workflow)) = sqlx::query_as::<_, Workflow>("SELECT * FROM workflows WHERE id = $1")
+            .bind(error_workflow_id)
+            .fetch_optional(&pool)
+            .await else {
+                eprintln!("Error workflow {} for '{}' not found", error_workflow_id, workflow_name);
+                return;
+            };
+
+        let nodes: Vec<Node> = serde_json::from_value(error_workflow.nodes.clone()).unwrap_or_default();
+        let edges: Vec<Edge> = serde_json::from_value(error_workflow.edges.clone()).unwrap_or_default();
+        let Some(trigger) = nodes.iter().find(|n| n.kind == "error-trigger") else {
+            eprintln!("Error workflow '{}' has no error-trigger node", error_workflow.name);
+            return;
+        };
+
+        let input = serde_json::json!({
+            "execution": {
+                "id": execution_id,
+                "lastNodeExecuted": last_node,
+                "error": { "message": error },
+            },
+            "workflow": {
+                "id": workflow_id,
+                "name": workflow_name,
+            },
+            "errorChain": chain,
+        });
+        run_workflow_from_node(&pool, error_workflow.id, &error_workflow.name, &nodes, &edges, trigger, input).await;
+    })
+}
+
+/// Image responses with inline `b64_json` data become one file item per image, so later nodes can use
+/// them like any other binary data. URL responses are returned as they are.
+fn image_output(response: serde_json::Value) -> serde_json::Value {
+    let Some(images) = response.get("data").and_then(|v| v.as_array()) else { return response };
+    if !images.iter().any(|image| image.get("b64_json").is_some()) {
+        return response;
+    }
+    let files = images.iter().enumerate().filter_map(|(i, image)| {
+        let data = binary::decode(image.get("b64_json")?.as_str()?)?;
+        let mime_type = images::sniff_image_type(&data).unwrap_or("image/png");
+        let extension = binary::extension(mime_type).unwrap_or("png");
+        let mut file = binary::file_output(&data, mime_type, &format!("image-{}.{}", i + 1, extension));
+        if let Some(revised) = image.get("revised_prompt") {
+            file["revisedPrompt"] = revised.clone();
+        }
+        Some(file)
+    });
+    serde_json::Value::Array(files.collect())
+}
+
+async fn execute_agent(pool: &Pool<Postgres>, node: &Node, all_nodes: &[Node], edges: &[Edge], input: &serde_json::Value, ctx: &ExecutionContext) -> Result<serde_json::Value, String> {
     let provider = node.config.get("provider").and_then(|v| v.as_str()).unwrap_or("openai");
-    let model = node.config.get("model").and_then(|v| v.as_str()).unwrap_or("gpt-4o");
+    let model = node.config.get("model").and_then(|v| v.as_str()).filter(|s| !s.is_empty()).unwrap_or(default_model(provider));
     let prompt_raw = node.config.get("prompt").and_then(|v| v.as_str()).ok_or("Prompt not specified")?;
     let prompt = interpolate_value(prompt_raw, input);
     let system_message_raw = node.config.get("systemMessage").and_then(|v| v.as_str());
     let system_message = system_message_raw.map(|s| interpolate_value(s, input));
 
+    // Conversation memory connected via the "memory" port
+    let memory_node = edges.iter()
+        .filter(|e| e.to == node.id && e.to_port.as_deref() == Some("memory"))
+        .filter_map(|e| all_nodes.iter().find(|n| n.id == e.from))
+        .find(|n| memory::MEMORY_KINDS.contains(&n.kind.as_str()));
+    let mut memory = match memory_node {
+        Some(m) => Some(AgentMemory::load(pool, m, memory_session_key(m, input, ctx)).await?),
+        None => None,
+    };
+
     // 1. Get connected tool nodes via the "tools" port
     let tool_nodes: Vec<&Node> = edges.iter()
         .filter(|e| e.to == node.id && e.to_port == Some("tools".to_string()))
         .filter_map(|e| all_nodes.iter().find(|n| n.id == e.from))
-        .filter(|n| n.kind == "tool")
         .collect();
 
-    let mut tools_schema = Vec::new();
+    let mut tools_schema: Vec<ToolDefinition> = Vec::new();
     let mut mcp_tools_map = std::collections::HashMap::new();
+    let mut node_tools: std::collections::HashMap<String, &Node> = std::collections::HashMap::new();
+    let mut engine_tool_nodes = Vec::new();
 
     for tool_node in &tool_nodes {
         // 1.1 Check if it's an MCP tool
@@ -1208,14 +1877,11 @@ async fn execute_agent(pool: &Pool<Postgres>, node: &Node, all_nodes: &[Node], e
                 let tools = fetch_mcp_tools(&server).await?;
                 if let Some(tool) = tools.into_iter().find(|t| t.name == target_tool_name) {
                     let full_name = format!("{}__{}", server.name, tool.name);
-                    tools_schema.push(serde_json::json!({
-                        "type": "function",
-                        "function": {
-                            "name": full_name,
-                            "description": tool.description.unwrap_or_default(),
-                            "parameters": tool.input_schema
-                        }
-                    }));
+                    tools_schema.push(ToolDefinition {
+                        name: full_name.clone(),
+                        description: tool.description.unwrap_or_default().to_string(),
+                        parameters: serde_json::json!(tool.input_schema),
+                    });
                     mcp_tools_map.insert(full_name, (server.clone(), tool.name.clone()));
                 } else {
                     return Err(format!("Tool '{}' not found on MCP server '{}'", target_tool_name, server.name));
@@ -1226,168 +1892,315 @@ async fn execute_agent(pool: &Pool<Postgres>, node: &Node, all_nodes: &[Node], e
         else if tool_node.kind == "rss-read-tool" {
             let tool_name = tool_node.config.get("toolName").and_then(|v| v.as_str()).unwrap_or("rss_reader");
             let description = tool_node.config.get("description").and_then(|v| v.as_str()).unwrap_or("Reads entries from an RSS feed.");
-            tools_schema.push(serde_json::json!({
-                "type": "function",
-                "function": {
-                    "name": tool_name,
-                    "description": description,
-                    "parameters": {
-                        "type": "object",
-                        "properties": {
-                            "query": { "type": "string", "description": "Optional search query to filter feed items" }
-                        }
+            tools_schema.push(ToolDefinition {
+                name: tool_name.to_string(),
+                description: description.to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "Optional search query to filter feed items" }
                     }
-                }
-            }));
+                }),
+            });
         }
-        else {
+        else if tool_node.kind == "tool" {
             // Static/Manual Tool Node (if any)
             let tool_name = tool_node.config.get("toolName").and_then(|v| v.as_str()).unwrap_or("unknown_tool");
             let description = tool_node.config.get("description").and_then(|v| v.as_str()).unwrap_or("No description");
-            tools_schema.push(serde_json::json!({
-                "type": "function",
-                "function": {
-                    "name": tool_name,
-                    "description": description,
-                    "parameters": {
-                        "type": "object",
-                        "properties": {
-                            "query": { "type": "string" }
-                        }
+            tools_schema.push(ToolDefinition {
+                name: tool_name.to_string(),
+                description: description.to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string" }
                     }
-                }
-            }));
+                }),
+            });
+        }
+        // 1.3 Any other node runs through the engine with the call arguments as its input
+        else {
+            engine_tool_nodes.push(*tool_node);
         }
     }
+    // Named once every other tool is known, so a clashing label gets a suffix instead of shadowing a tool
+    for tool_node in engine_tool_nodes {
+        let tool_name = unique_tool_name(node_tool_name(tool_node), &tools_schema);
+        tools_schema.push(ToolDefinition {
+            name: tool_name.clone(),
+            description: tool_node.config.get("toolDescription").and_then(|v| v.as_str()).filter(|s| !s.trim().is_empty())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| format!("Runs the '{}' {} node.", tool_node.label, tool_node.kind)),
+            parameters: node_tool_parameters(tool_node),
+        });
+        node_tools.insert(tool_name, tool_node);
+    }
 
-    let api_key = if provider == "openai" {
-        get_api_key(pool, node, "openai", "OPENAI_API_KEY").await?
-    } else {
-        get_api_key(pool, node, "openrouter", "OPENROUTER_API_KEY").await?
-    };
+    let client = llm_provider(pool, node, provider).await?;
 
     let mut current_messages = Vec::new();
     if let Some(s) = system_message {
-        current_messages.push(OpenAiMessage { role: "system".to_string(), content: s, tool_calls: None, tool_call_id: None });
+        current_messages.push(ChatMessage::system(s));
     }
-    current_messages.push(OpenAiMessage { role: "user".to_string(), content: prompt, tool_calls: None, tool_call_id: None });
+    if let Some(memory) = memory.as_mut() {
+        memory.compact(client.as_ref(), model, node, ctx).await?;
+        current_messages.extend(memory.messages());
+    }
+    current_messages.push(ChatMessage::user(prompt.clone()).with_parts(images::image_parts(node, input)?));
 
-    let tools_value = if !tools_schema.is_empty() {
-        Some(serde_json::Value::Array(tools_schema))
-    } else {
-        None
-    };
+    let max_iterations = node.config.get("maxIterations").and_then(|v| v.as_u64()).filter(|n| *n > 0).unwrap_or(10);
+    let output_schema = OutputSchema::from_node(node)?;
+    let mut repairs_left = output_schema.as_ref().map(|s| s.retries).unwrap_or(0);
+    let (mcp_tools_map, node_tools, tool_nodes) = (&mcp_tools_map, &node_tools, &tool_nodes);
+    // One entry per model turn: what the model answered, the tools it called and what they returned
+    let mut trace = Vec::new();
 
-    for _ in 0..10 {
-        let response = if provider == "openai" {
-            let client = OpenAiClient::new(api_key.clone());
-            client.generate(model, current_messages.clone(), None, None, tools_value.clone()).await.map_err(|e| e.to_string())?
-        } else {
-            let client = OpenRouterClient::new(api_key.clone());
-            let or_messages: Vec<OpenRouterMessage> = current_messages.iter().map(|m| OpenRouterMessage {
-                role: m.role.clone(),
-                content: m.content.clone(),
-                tool_calls: m.tool_calls.clone(),
-                tool_call_id: m.tool_call_id.clone()
-            }).collect();
-            let or_request = OpenRouterRequest {
-                model: model.to_string(),
-                messages: or_messages,
-                temperature: None,
-                max_tokens: None,
-                top_p: None,
-                frequency_penalty: None,
-                presence_penalty: None,
-                response_format: None,
-                tools: tools_value.clone(),
-                tool_choice: None
-            };
-            client.generate(or_request).await.map_err(|e| e.to_string())?
+    for iteration in 1..=max_iterations {
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: current_messages.clone(),
+            tools: tools_schema.clone(),
+            response_format: output_schema.as_ref().map(|s| s.response_format()),
+            ..Default::default()
         };
+        let turn_start = std::time::Instant::now();
+        let response = stream_chat(pool, client.as_ref(), request, output_schema.as_ref(), node, ctx).await?;
+        let model_latency = turn_start.elapsed().as_millis() as u64;
+        let message = response.message;
 
-        let message = response.get("choices")
-            .and_then(|c| c.get(0))
-            .and_then(|m| m.get("message"))
-            .ok_or("Invalid LLM response")?;
-
-        if let Some(tool_calls) = message.get("tool_calls") {
-            current_messages.push(OpenAiMessage {
-                role: "assistant".to_string(),
-                content: message.get("content").and_then(|v| v.as_str()).unwrap_or("").to_string(),
-                tool_calls: Some(tool_calls.clone()),
-                tool_call_id: None
-            });
+        if message.tool_calls.is_empty() {
+            let parsed = output_schema.as_ref().map(|schema| schema.parse(&message.content));
+            trace.push(serde_json::json!({
+                "iteration": iteration,
+                "content": message.content,
+                "finishReason": response.finish_reason,
+                "latencyMs": model_latency,
+                "toolCalls": [],
+                "schemaErrors": parsed.as_ref().and_then(|p| p.as_ref().err()),
+            }));
+            let output = match parsed {
+                Some(Err(errors)) if repairs_left > 0 => {
+                    repairs_left -= 1;
+                    let repair = output_schema.as_ref().map(|s| s.repair_prompt(&errors)).unwrap_or_default();
+                    current_messages.push(message);
+                    current_messages.push(ChatMessage::user(repair));
+                    continue;
+                }
+                Some(Err(errors)) => {
+                    ctx.failures.record(node, NodeRunDetails { trace: Some(serde_json::json!(trace)), ..Default::default() });
+                    return Err(format!("Agent reply did not match the output schema:\n{}", errors));
+                }
+                Some(Ok(value)) => Some(value),
+                None => None,
+            };
+            if let Some(memory) = memory.as_mut() {
+                memory.save(pool, prompt, message.content.clone()).await?;
+            }
+            let mut result = serde_json::json!({ "text": message.content, "iterations": iteration, "trace": trace });
+            if let Some(output) = output {
+                result["output"] = output;
+            }
+            return Ok(result);
+        }
 
-            for call in tool_calls.as_array().unwrap_or(&vec![]) {
-                let call_id = call.get("id").and_then(|v| v.as_str()).unwrap_or("");
-                let func_name = call.get("function").and_then(|f| f.get("name")).and_then(|v| v.as_str()).unwrap_or("");
-                let arguments = call.get("function").and_then(|f| f.get("arguments")).and_then(|v| v.as_str()).unwrap_or("{}");
-                let args_json: serde_json::Value = serde_json::from_str(arguments).unwrap_or(serde_json::json!({}));
+        // The calls of one turn don't depend on each other, so they run concurrently
+        let results = futures_util::future::join_all(message.tool_calls.iter().map(|call| async move {
+            let call_start = std::time::Instant::now();
+            let func_name = call.name.as_str();
+            let args_json: serde_json::Value = serde_json::from_str(&call.arguments).unwrap_or(serde_json::json!({}));
 
-                let tool_result = if let Some((server, original_name)) = mcp_tools_map.get(func_name) {
-                    match call_mcp_tool(server, original_name, args_json).await {
-                        Ok(res) => res.to_string(),
-                        Err(e) => format!("Error calling MCP tool: {}", e),
-                    }
-                } else {
-                    let tool_node = tool_nodes.iter().find(|tn| tn.config.get("toolName").and_then(|v| v.as_str()) == Some(func_name));
-                    
-                    if let Some(tn) = tool_node {
-                        if tn.kind == "rss-read-tool" {
-                            // Execute RSS Read logic for Agent (Inlined to avoid async recursion)
-                            let url_raw = tn.config.get("url").and_then(|v| v.as_str()).unwrap_or("");
-                            if url_raw.is_empty() {
-                                "Error: RSS Feed URL not configured in tool node".to_string()
-                            } else {
-                                let url = interpolate_value(url_raw, input);
-                                let client = reqwest::Client::new();
-                                match client.get(&url).send().await {
-                                    Ok(resp) => {
-                                        match resp.bytes().await {
-                                            Ok(content) => {
-                                                match feed_rs::parser::parse(&content[..]) {
-                                                    Ok(feed) => {
-                                                        let mut items = Vec::new();
-                                                        for entry in feed.entries {
-                                                            items.push(serde_json::json!({
-                                                                "title": entry.title.map(|t| t.content),
-                                                                "link": entry.links.first().map(|l| l.href.clone()),
-                                                                "published": entry.published,
-                                                            }));
-                                                        }
-                                                        serde_json::json!(items).to_string()
-                                                    },
-                                                    Err(e) => format!("Error parsing RSS: {}", e)
-                                                }
-                                            },
-                                            Err(e) => format!("Error reading bytes: {}", e)
-                                        }
-                                    },
-                                    Err(e) => format!("Error fetching feed: {}", e)
-                                }
-                            }
-                        } else {
-                            format!("Result from {}: Action completed successfully.", func_name)
-                        }
-                    } else {
-                        format!("Error: Tool '{}' not found", func_name)
-                    }
-                };
+            let tool_result = if let Some((server, original_name)) = mcp_tools_map.get(func_name) {
+                match call_mcp_tool(server, original_name, args_json.clone()).await {
+                    Ok(res) => res.to_string(),
+                    Err(e) => format!("Error calling MCP tool: {}", e),
+                }
+            } else if let Some(tool_node) = node_tools.get(func_name) {
+                run_node_tool(pool, tool_node, all_nodes, edges, args_json.clone(), ctx).await
+            } else {
+                match tool_nodes.iter().find(|tn| tn.config.get("toolName").and_then(|v| v.as_str()) == Some(func_name)) {
+                    Some(tn) if tn.kind == "rss-read-tool" => read_rss_tool(tn, input).await,
+                    Some(_) => format!("Result from {}: Action completed successfully.", func_name),
+                    None => format!("Error: Tool '{}' not found", func_name),
+                }
+            };
+            (args_json, tool_result, call_start.elapsed().as_millis() as u64)
+        })).await;
 
-                current_messages.push(OpenAiMessage {
-                    role: "tool".to_string(),
-                    content: tool_result,
-                    tool_calls: None,
-                    tool_call_id: Some(call_id.to_string())
-                });
+        let mut calls = Vec::new();
+        let mut tool_messages = Vec::new();
+        for (call, (arguments, result, latency)) in message.tool_calls.iter().zip(results) {
+            calls.push(serde_json::json!({
+                "id": call.id,
+                "name": call.name,
+                "arguments": arguments,
+                "result": result,
+                "latencyMs": latency,
+            }));
+            tool_messages.push(ChatMessage::tool(call.id.clone(), result));
+        }
+        trace.push(serde_json::json!({
+            "iteration": iteration,
+            "content": message.content,
+            "finishReason": response.finish_reason,
+            "latencyMs": model_latency,
+            "toolCalls": calls,
+        }));
+        current_messages.push(message);
+        current_messages.extend(tool_messages);
+    }
+
+    // The turns so far show what the agent was stuck on
+    ctx.failures.record(node, NodeRunDetails { trace: Some(serde_json::json!(trace)), ..Default::default() });
+    Err(format!("Agent reached maximum iterations ({})", max_iterations))
+}
+
+/// Reads the feed of an `rss-read-tool` node for an agent.
+async fn read_rss_tool(tool_node: &Node, input: &serde_json::Value) -> String {
+    let url_raw = tool_node.config.get("url").and_then(|v| v.as_str()).unwrap_or("");
+    if url_raw.is_empty() {
+        return "Error: RSS Feed URL not configured in tool node".to_string();
+    }
+    let url = interpolate_value(url_raw, input);
+    let content = match reqwest::Client::new().get(&url).send().await {
+        Ok(resp) => match resp.bytes().await {
+            Ok(content) => content,
+            Err(e) => return format!("Error reading bytes: {}", e),
+        },
+        Err(e) => return format!("Error fetching feed: {}", e),
+    };
+    match feed_rs::parser::parse(&content[..]) {
+        Ok(feed) => {
+            let items: Vec<serde_json::Value> = feed.entries.into_iter().map(|entry| serde_json::json!({
+                "title": entry.title.map(|t| t.content),
+                "link": entry.links.first().map(|l| l.href.clone()),
+                "published": entry.published,
+            })).collect();
+            serde_json::json!(items).to_string()
+        }
+        Err(e) => format!("Error parsing RSS: {}", e),
+    }
+}
+
+/// Ports through which sub-nodes attach to an agent. The agent reads those nodes; they don't run as steps.
+const SUB_NODE_PORTS: [&str; 2] = ["tools", "memory"];
+
+/// Whether `edge` attaches a sub-node to an agent rather than passing data along.
+pub fn is_sub_node_edge(edge: &Edge) -> bool {
+    edge.to_port.as_deref().is_some_and(|p| SUB_NODE_PORTS.contains(&p))
+}
+
+/// Whether `node` only feeds agents as a tool or memory, so it must not start an execution of its own.
+pub fn is_sub_node(node: &Node, edges: &[Edge]) -> bool {
+    let mut outgoing = edges.iter().filter(|e| e.from == node.id).peekable();
+    outgoing.peek().is_some() && outgoing.all(is_sub_node_edge)
+}
+
+/// Function name a node on an agent's `tools` port is offered under: its `toolName`, else its label,
+/// reduced to the characters function names allow.
+fn node_tool_name(node: &Node) -> String {
+    let name = node.config.get("toolName").and_then(|v| v.as_str()).filter(|s| !s.trim().is_empty()).unwrap_or(&node.label);
+    let name: String = name.trim().chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(64)
+        .collect();
+    if name.is_empty() { format!("{}_tool", node.kind.replace('-', "_")) } else { name }
+}
+
+/// Returns `name`, or `name_2`, `name_3`, ... when a tool of that name is already offered.
+fn unique_tool_name(name: String, taken: &[ToolDefinition]) -> String {
+    let is_taken = |candidate: &str| taken.iter().any(|t| t.name == candidate);
+    if !is_taken(&name) { return name; }
+    (2..).map(|n| {
+        let suffix = format!("_{}", n);
+        let base: String = name.chars().take(64 - suffix.len()).collect();
+        base + &suffix
+    }).find(|candidate| !is_taken(candidate)).unwrap()
+}
+
+/// Parameters of a node tool: one string argument per `{{ $input.<name> }}` placeholder in the node's
+/// configuration. The call arguments become the node's input, so the placeholders resolve to them.
+/// Nodes without placeholders (such as code reading `$input`) accept any object.
+fn node_tool_parameters(node: &Node) -> serde_json::Value {
+    let mut names = Vec::new();
+    collect_input_placeholders(&node.config, &mut names);
+    if names.is_empty() {
+        return serde_json::json!({ "type": "object", "properties": {}, "additionalProperties": true });
+    }
+    let properties: serde_json::Map<String, serde_json::Value> = names.iter()
+        .map(|name| (name.clone(), serde_json::json!({ "type": "string" })))
+        .collect();
+    serde_json::json!({ "type": "object", "properties": properties, "required": names })
+}
+
+fn collect_input_placeholders(value: &serde_json::Value, names: &mut Vec<String>) {
+    match value {
+        serde_json::Value::String(s) => {
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start..].find("}}") else { break; };
+                let inner = rest[start + 2..start + end].trim();
+                if let Some(name) = inner.strip_prefix("$input.").and_then(|path| path.split('.').next())
+                    && !name.is_empty() && !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+                rest = &rest[start + end + 2..];
             }
-        } else {
-            let text = message.get("content").and_then(|v| v.as_str()).unwrap_or("");
-            return Ok(serde_json::json!({ "text": text }));
         }
+        serde_json::Value::Array(items) => items.iter().for_each(|v| collect_input_placeholders(v, names)),
+        serde_json::Value::Object(map) => map.values().for_each(|v| collect_input_placeholders(v, names)),
+        _ => {}
     }
+}
+
+/// Runs a node attached to an agent's `tools` port with the call arguments as its input and returns what
+/// the model gets to see. The run is published like a workflow step so editors can show it.
+/// Boxed because the node runs through `execute_single_node` again.
+fn run_node_tool<'a>(
+    pool: &'a Pool<Postgres>,
+    node: &'a Node,
+    all_nodes: &'a [Node],
+    edges: &'a [Edge],
+    arguments: serde_json::Value,
+    ctx: &'a ExecutionContext,
+) -> Pin<Box<dyn Future<Output = String> + Send + 'a>> {
+    Box::pin(async move {
+        let start = std::time::Instant::now();
+        events::publish(ctx.execution_id, ExecutionEvent::NodeStarted { node_id: node.id.to_string(), node_name: node.label.clone() });
+        let mut result = execute_single_node(pool, node, all_nodes, edges, &arguments, ctx).await.map(take_details);
+        let usage = usage::finish_node(pool, ctx, node).await;
+        if let Ok((_, details)) = &mut result {
+            details.usage = usage.clone();
+        }
+        let (output, error) = match &result {
+            Ok((output, _)) => (Some(output.clone()), None),
+            Err(e) => (None, Some(e.clone())),
+        };
+        events::publish(ctx.execution_id, ExecutionEvent::NodeFinished { result: NodeExecutionResult {
+            node_id: node.id.to_string(),
+            node_name: node.label.clone(),
+            success: result.is_ok(),
+            output,
+            error,
+            execution_time_ms: start.elapsed().as_millis() as u64,
+            details: result.as_ref().map(|(_, details)| details.clone()).unwrap_or(NodeRunDetails { usage, ..Default::default() }),
+        }});
+        match result {
+            Ok((serde_json::Value::String(text), _)) => text,
+            Ok((output, _)) => output.to_string(),
+            Err(e) => format!("Error: {}", e),
+        }
+    })
+}
 
-    Err("Agent reached maximum iterations".to_string())
+/// Session a memory node remembers: its `sessionKey` expression, else the chat session of the input,
+/// else one session for the current execution, so runs for different users never share history.
+fn memory_session_key(memory_node: &Node, input: &serde_json::Value, ctx: &ExecutionContext) -> String {
+    memory_node.config.get("sessionKey")
+        .and_then(|v| v.as_str())
+        .map(|s| interpolate_value(s, input))
+        .filter(|s| !s.trim().is_empty())
+        .or_else(|| input.get("sessionId").and_then(|v| v.as_str()).map(|s| s.to_string()))
+        .unwrap_or_else(|| format!("execution:{}", ctx.execution_id))
 }
 
 async fn fetch_mcp_tools(server: &McpServer) -> Result<Vec<rmcp::model::Tool>, String> {
@@ -0,0 +1,64 @@
+use crate::models::Node;
+
+/// Repair attempts after the first reply when the node doesn't set `outputSchemaRetries`.
+const DEFAULT_RETRIES: usize = 2;
+
+/// JSON Schema an LLM node's reply must match, from the node's `outputSchema` config.
+pub struct OutputSchema {
+    schema: serde_json::Value,
+    validator: jsonschema::Validator,
+    /// How many times the model is asked to fix a reply that doesn't match.
+    pub retries: usize,
+}
+
+impl OutputSchema {
+    /// Reads `outputSchema`, given either as an object or as JSON text. Nodes without one return `None`.
+    pub fn from_node(node: &Node) -> Result<Option<Self>, String> {
+        let schema = match node.config.get("outputSchema") {
+            None | Some(serde_json::Value::Null) => return Ok(None),
+            Some(serde_json::Value::String(text)) if text.trim().is_empty() => return Ok(None),
+            Some(serde_json::Value::String(text)) => serde_json::from_str(text).map_err(|e| format!("Invalid output schema: {}", e))?,
+            Some(schema) => schema.clone(),
+        };
+        let validator = jsonschema::validator_for(&schema).map_err(|e| format!("Invalid output schema: {}", e))?;
+        let retries = node.config.get("outputSchemaRetries").and_then(|v| v.as_u64()).map(|v| v as usize).unwrap_or(DEFAULT_RETRIES);
+        Ok(Some(Self { schema, validator, retries }))
+    }
+
+    /// The `response_format` asking OpenAI-style APIs for JSON that follows the schema.
+    pub fn response_format(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "json_schema",
+            "json_schema": { "name": "output", "schema": self.schema, "strict": false },
+        })
+    }
+
+    /// Parses a reply and validates it. The error lists every mismatch, ready to be sent back to the model.
+    pub fn parse(&self, content: &str) -> Result<serde_json::Value, String> {
+        let value: serde_json::Value = serde_json::from_str(strip_code_fence(content))
+            .map_err(|e| format!("- The reply is not valid JSON: {}", e))?;
+        let errors: Vec<String> = self.validator.iter_errors(&value)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                format!("- {}: {}", if path.is_empty() { "/" } else { &path }, e)
+            })
+            .collect();
+        if errors.is_empty() { Ok(value) } else { Err(errors.join("\n")) }
+    }
+
+    /// Follow-up message asking the model to fix a reply that failed [`OutputSchema::parse`].
+    pub fn repair_prompt(&self, errors: &str) -> String {
+        format!(
+            "Your reply does not match the required JSON Schema:\n{}\n\nReply again with only the corrected JSON. The schema is:\n{}",
+            errors, self.schema
+        )
+    }
+}
+
+/// Models sometimes wrap JSON in a Markdown code block even when asked not to.
+fn strip_code_fence(content: &str) -> &str {
+    let trimmed = content.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else { return trimmed; };
+    let rest = rest.strip_prefix("json").unwrap_or(rest);
+    rest.strip_suffix("```").unwrap_or(rest).trim()
+}
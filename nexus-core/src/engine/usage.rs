@@ -0,0 +1,145 @@
+use std::sync::{Arc, Mutex};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+use crate::clients::llm::TokenUsage;
+use crate::models::{KnowledgeBase, ModelPrice, ModelUsage, Node, NodeUsage};
+use super::ExecutionContext;
+
+#[derive(Debug, Clone)]
+struct LlmCall {
+    node_id: Uuid,
+    provider: String,
+    model: String,
+    /// `None` for calls answered from the response cache.
+    usage: Option<TokenUsage>,
+}
+
+/// LLM calls made during an execution that haven't been attributed to a node result yet.
+#[derive(Debug, Clone, Default)]
+pub struct UsageRecorder(Arc<Mutex<Vec<LlmCall>>>);
+
+impl UsageRecorder {
+    pub fn record(&self, node: &Node, provider: &str, model: &str, usage: &TokenUsage) {
+        self.0.lock().unwrap().push(LlmCall {
+            node_id: node.id,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            usage: Some(usage.clone()),
+        });
+    }
+
+    pub fn record_cache_hit(&self, node: &Node, provider: &str, model: &str) {
+        self.0.lock().unwrap().push(LlmCall { node_id: node.id, provider: provider.to_string(), model: model.to_string(), usage: None });
+    }
+
+    fn take(&self, node_id: Uuid) -> Vec<LlmCall> {
+        let mut calls = self.0.lock().unwrap();
+        let (taken, kept) = calls.drain(..).partition(|c| c.node_id == node_id);
+        *calls = kept;
+        taken
+    }
+}
+
+/// Prices the LLM calls `node` made in this run, stores them in `llm_usage` and returns the totals
+/// for its `NodeExecutionResult`. Cache hits are counted but cost nothing. Nodes that made no calls return `None`.
+pub async fn finish_node(pool: &Pool<Postgres>, ctx: &ExecutionContext, node: &Node) -> Option<NodeUsage> {
+    let calls = ctx.usage.take(node.id);
+    if calls.is_empty() { return None; }
+
+    let prices = load_prices(pool).await;
+    let mut usage = NodeUsage::default();
+    for call in calls {
+        let Some(tokens) = call.usage else {
+            usage.cache_hits += 1;
+            continue;
+        };
+        let entry = match usage.models.iter_mut().find(|m| m.provider == call.provider && m.model == call.model) {
+            Some(entry) => entry,
+            None => {
+                usage.models.push(ModelUsage { provider: call.provider.clone(), model: call.model.clone(), ..Default::default() });
+                usage.models.last_mut().unwrap()
+            }
+        };
+        entry.calls += 1;
+        entry.input_tokens += tokens.input_tokens;
+        entry.output_tokens += tokens.output_tokens;
+    }
+    for entry in &mut usage.models {
+        entry.cost_usd = price_for(&prices, &entry.model).map(|p| {
+            (entry.input_tokens as f64 * p.input_per_million + entry.output_tokens as f64 * p.output_per_million) / 1_000_000.0
+        });
+        usage.input_tokens += entry.input_tokens;
+        usage.output_tokens += entry.output_tokens;
+        usage.cost_usd += entry.cost_usd.unwrap_or(0.0);
+
+        insert(pool, Some(ctx.execution_id), &ctx.workflow_id, &ctx.workflow_name, &node.id.to_string(), &node.label, entry).await;
+    }
+    Some(usage)
+}
+
+/// Stores the embedding call of a knowledge base made outside an execution, when ingesting a document or searching
+/// through the API. Rows have no execution and use `knowledge-base:<id>` as their workflow id.
+pub async fn record_knowledge_base(pool: &Pool<Postgres>, kb: &KnowledgeBase, source_id: &str, source_name: &str, tokens: &TokenUsage) {
+    let prices = load_prices(pool).await;
+    let entry = ModelUsage {
+        provider: kb.embedding_provider.clone(),
+        model: kb.embedding_model.clone(),
+        calls: 1,
+        input_tokens: tokens.input_tokens,
+        output_tokens: tokens.output_tokens,
+        cost_usd: price_for(&prices, &kb.embedding_model).map(|p| {
+            (tokens.input_tokens as f64 * p.input_per_million + tokens.output_tokens as f64 * p.output_per_million) / 1_000_000.0
+        }),
+    };
+    insert(pool, None, &format!("knowledge-base:{}", kb.id), &kb.name, source_id, source_name, &entry).await;
+}
+
+async fn insert(pool: &Pool<Postgres>, execution_id: Option<Uuid>, workflow_id: &str, workflow_name: &str, node_id: &str, node_name: &str, entry: &ModelUsage) {
+    let _ = sqlx::query(
+        "INSERT INTO llm_usage (id, execution_id, workflow_id, workflow_name, node_id, node_name, provider, model, calls, input_tokens, output_tokens, cost_usd)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)"
+    )
+    .bind(Uuid::new_v4())
+    .bind(execution_id)
+    .bind(workflow_id)
+    .bind(workflow_name)
+    .bind(node_id)
+    .bind(node_name)
+    .bind(&entry.provider)
+    .bind(&entry.model)
+    .bind(entry.calls as i32)
+    .bind(entry.input_tokens as i64)
+    .bind(entry.output_tokens as i64)
+    .bind(entry.cost_usd)
+    .execute(pool)
+    .await
+    .map_err(|e| eprintln!("Failed to record LLM usage: {}", e));
+}
+
+/// Copies the usage totals of an execution from `llm_usage` onto its `executions` row.
+pub async fn update_execution(pool: &Pool<Postgres>, execution_id: Uuid) {
+    let _ = sqlx::query(
+        "UPDATE executions SET
+            input_tokens = u.input_tokens, output_tokens = u.output_tokens, cost_usd = u.cost_usd
+         FROM (SELECT COALESCE(SUM(input_tokens), 0)::BIGINT AS input_tokens, COALESCE(SUM(output_tokens), 0)::BIGINT AS output_tokens,
+                      COALESCE(SUM(cost_usd), 0)::DOUBLE PRECISION AS cost_usd
+               FROM llm_usage WHERE execution_id = $1) u
+         WHERE id = $1"
+    )
+    .bind(execution_id)
+    .execute(pool)
+    .await;
+}
+
+async fn load_prices(pool: &Pool<Postgres>) -> Vec<ModelPrice> {
+    sqlx::query_as::<_, ModelPrice>("SELECT * FROM model_prices").fetch_all(pool).await.unwrap_or_default()
+}
+
+/// Finds the price of `model`: an exact entry, else the entry without an OpenRouter-style vendor prefix,
+/// else the longest entry the model name starts with (so dated snapshots use the base model's price).
+fn price_for<'a>(prices: &'a [ModelPrice], model: &str) -> Option<&'a ModelPrice> {
+    let bare = model.rsplit('/').next().unwrap_or(model);
+    prices.iter().find(|p| p.model == model)
+        .or_else(|| prices.iter().find(|p| p.model == bare))
+        .or_else(|| prices.iter().filter(|p| bare.starts_with(&p.model)).max_by_key(|p| p.model.len()))
+}
@@ -1,15 +1,19 @@
 use axum::{
     Json,
-    extract::{Path, State, Form},
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    body::Bytes,
+    extract::{Path, Query, State, Form, Multipart},
+    http::{HeaderMap, Method, StatusCode},
+    response::{Html, IntoResponse, Response, sse::{Event, KeepAlive, Sse}},
 };
+use std::collections::HashMap;
 use uuid::Uuid;
 use crate::state::AppState;
 use crate::models::*;
-use crate::engine::execute_single_node;
-use crate::clients::openai::OpenAiMessage;
-use crate::clients::openrouter::{OpenRouterMessage, OpenRouterRequest};
+use crate::engine::{error_chain, execute_single_node, failure_details, is_sub_node, is_sub_node_edge, notify_error_workflow, run_workflow_execution, run_workflow_from_node, take_details, ExecutionContext};
+use crate::engine::events::{self, ExecutionEvent};
+use crate::engine::{knowledge, usage};
+use crate::triggers::form::{self, UploadedFile};
+use crate::clients::{registry, ChatMessage, ChatRequest, ProviderSettings};
 use serde::{Deserialize, Serialize};
 
 pub async fn handle_slack_events(
@@ -29,7 +33,7 @@ pub async fn handle_slack_events(
     }
 
     // 3. Find Workflows with slack-trigger for this event
-    let workflows = match sqlx::query_as::<_, Workflow>("SELECT * FROM workflows")
+    let workflows = match sqlx::query_as::<_, Workflow>("SELECT * FROM workflows WHERE active = TRUE")
         .fetch_all(&state.db)
         .await {
             Ok(w) => w,
@@ -66,66 +70,7 @@ pub async fn handle_slack_events(
             let start_node = node.clone();
             
             tokio::spawn(async move {
-                let execution_id = Uuid::new_v4();
-                let start_time = chrono::Utc::now();
-                let _ = sqlx::query("INSERT INTO executions (id, workflow_id, workflow_name, status, results, start_time) VALUES ($1, $2, $3, $4, $5, $6)")
-                    .bind(execution_id)
-                    .bind(workflow_id)
-                    .bind(workflow_name)
-                    .bind("running")
-                    .bind(serde_json::json!([]))
-                    .bind(start_time)
-                    .execute(&db)
-                    .await;
-
-                let mut results = Vec::new();
-                let mut queue = std::collections::VecDeque::new();
-                queue.push_back((start_node, event_data));
-
-                while let Some((current_node, current_input)) = queue.pop_front() {
-                    let node_start_time = std::time::Instant::now();
-                    let result = execute_single_node(&db, &current_node, &all_nodes, &all_edges, &current_input).await;
-                    
-                    match result {
-                        Ok(output) => {
-                            results.push(NodeExecutionResult {
-                                node_id: current_node.id.to_string(),
-                                node_name: current_node.label.clone(),
-                                success: true,
-                                output: Some(output.clone()),
-                                error: None,
-                                execution_time_ms: node_start_time.elapsed().as_millis() as u64,
-                            });
-                            
-                            // Find next nodes
-                            for edge in &all_edges {
-                                if edge.from == current_node.id {
-                                    if let Some(target_node) = all_nodes.iter().find(|n| n.id == edge.to) {
-                                        queue.push_back((target_node.clone(), output.clone()));
-                                    }
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            results.push(NodeExecutionResult {
-                                node_id: current_node.id.to_string(),
-                                node_name: current_node.label.clone(),
-                                success: false,
-                                output: None,
-                                error: Some(e),
-                                execution_time_ms: node_start_time.elapsed().as_millis() as u64,
-                            });
-                            break;
-                        }
-                    }
-                }
-
-                let _ = sqlx::query("UPDATE executions SET status = $1, results = $2, finished_at = NOW() WHERE id = $3")
-                    .bind("completed")
-                    .bind(serde_json::to_value(&results).unwrap_or_default())
-                    .bind(execution_id)
-                    .execute(&db)
-                    .await;
+                run_workflow_from_node(&db, workflow_id, &workflow_name, &all_nodes, &all_edges, &start_node, event_data).await;
             });
         }
     }
@@ -133,6 +78,70 @@ pub async fn handle_slack_events(
     StatusCode::OK.into_response()
 }
 
+pub async fn show_form(Path(path): Path<String>, State(state): State<AppState>) -> Response {
+    let Some(ctx) = state.triggers.form(&path) else {
+        return (StatusCode::NOT_FOUND, Html(form::render_result("Form not found", &serde_json::json!("This form does not exist or its workflow is not active.")))).into_response();
+    };
+    match form::fields(&ctx.node.config) {
+        Ok(fields) => Html(form::render_form(&ctx.node.config, &fields, &HashMap::new(), &[])).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Html(form::render_result("Form unavailable", &serde_json::json!(e)))).into_response(),
+    }
+}
+
+pub async fn submit_form(Path(path): Path<String>, State(state): State<AppState>, mut multipart: Multipart) -> Response {
+    let Some(ctx) = state.triggers.form(&path) else {
+        return (StatusCode::NOT_FOUND, Html(form::render_result("Form not found", &serde_json::json!("This form does not exist or its workflow is not active.")))).into_response();
+    };
+    let fields = match form::fields(&ctx.node.config) {
+        Ok(f) => f,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Html(form::render_result("Form unavailable", &serde_json::json!(e)))).into_response(),
+    };
+
+    let mut values = HashMap::new();
+    let mut files = HashMap::new();
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) => {
+                let Some(name) = field.name().map(|s| s.to_string()) else { continue; };
+                let file_name = field.file_name().map(|s| s.to_string());
+                let mime_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+                let data = match field.bytes().await {
+                    Ok(b) => b,
+                    Err(e) => return (StatusCode::BAD_REQUEST, Html(form::render_result("Invalid submission", &serde_json::json!(e.to_string())))).into_response(),
+                };
+                match file_name {
+                    // Browsers send an empty part for file inputs left blank
+                    Some(f) if !f.is_empty() && !data.is_empty() => { files.insert(name, UploadedFile { file_name: f, mime_type, data: data.to_vec() }); }
+                    Some(_) => {}
+                    None => { values.insert(name, String::from_utf8_lossy(&data).to_string()); }
+                }
+            }
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, Html(form::render_result("Invalid submission", &serde_json::json!(e.to_string())))).into_response(),
+        }
+    }
+
+    let input = match form::validate_submission(&fields, &values, &files) {
+        Ok(v) => v,
+        Err(errors) => return (StatusCode::BAD_REQUEST, Html(form::render_form(&ctx.node.config, &fields, &values, &errors))).into_response(),
+    };
+
+    let title = ctx.node.config.get("formTitle").and_then(|v| v.as_str()).unwrap_or("Form").to_string();
+    let response_mode = ctx.node.config.get("responseMode").and_then(|v| v.as_str()).unwrap_or("onReceived");
+    if response_mode == "lastNode" {
+        let (_, results) = ctx.emit(input).await;
+        return match form::final_result(&results, &ctx.edges) {
+            Some(last) if last.success => Html(form::render_result(&title, last.output.as_ref().unwrap_or(&serde_json::Value::Null))).into_response(),
+            Some(last) => (StatusCode::INTERNAL_SERVER_ERROR, Html(form::render_result(&title, &serde_json::json!(format!("The workflow failed: {}", last.error.clone().unwrap_or_default()))))).into_response(),
+            None => Html(form::render_result(&title, &serde_json::json!("Workflow finished without output."))).into_response(),
+        };
+    }
+
+    let message = ctx.node.config.get("responseText").and_then(|v| v.as_str()).unwrap_or("Thanks, your response has been submitted.").to_string();
+    tokio::spawn(async move { ctx.emit(input).await; });
+    Html(form::render_result(&title, &serde_json::json!(message))).into_response()
+}
+
 pub async fn handle_slack_interactive(
     State(state): State<AppState>,
     Form(payload): Form<serde_json::Value>,
@@ -182,12 +191,16 @@ pub async fn handle_slack_interactive(
             }
 
             let db = state.db.clone();
+            let mut ctx = ExecutionContext::from_results(record.id, record.workflow_id.clone(), record.workflow_name.clone(), &results);
             tokio::spawn(async move {
                 let mut success = true;
                 while let Some((node, input_data)) = execution_queue.pop_front() {
                     let node_start_time = std::time::Instant::now();
-                    match execute_single_node(&db, &node, &nodes_list, &edges_list, &input_data).await {
+                    match execute_single_node(&db, &node, &nodes_list, &edges_list, &input_data, &ctx).await {
                         Ok(output) => {
+                            let (output, mut details) = take_details(output);
+                            details.usage = usage::finish_node(&db, &ctx, &node).await;
+                            ctx.record(&node, &output);
                             results.push(NodeExecutionResult {
                                 node_id: node.id.to_string(),
                                 node_name: node.label.clone(),
@@ -195,10 +208,11 @@ pub async fn handle_slack_interactive(
                                 output: Some(output.clone()),
                                 error: None,
                                 execution_time_ms: node_start_time.elapsed().as_millis() as u64,
+                                details,
                             });
 
                             for edge in &edges_list {
-                                if edge.from == node.id {
+                                if edge.from == node.id && !is_sub_node_edge(edge) {
                                     if let Some(next_node) = nodes_list.iter().find(|n| n.id == edge.to) {
                                         execution_queue.push_back((next_node.clone(), output.clone()));
                                     }
@@ -214,6 +228,7 @@ pub async fn handle_slack_interactive(
                                 output: None,
                                 error: Some(e),
                                 execution_time_ms: node_start_time.elapsed().as_millis() as u64,
+                                details: failure_details(&db, &ctx, &node).await,
                             });
                             break;
                         }
@@ -221,13 +236,19 @@ pub async fn handle_slack_interactive(
                 }
 
                 let _ = sqlx::query(
-                    "UPDATE executions SET status = $1, results = $2, finished_at = NOW(), snapshot = NULL WHERE id = $3"
+                    "UPDATE executions SET status = $1, results = $2, end_time = NOW(), snapshot = NULL WHERE id = $3"
                 )
                 .bind(if success { "success" } else { "failed" })
                 .bind(serde_json::to_value(&results).unwrap_or_default())
                 .bind(record.id)
                 .execute(&db)
                 .await;
+                usage::update_execution(&db, record.id).await;
+
+                if !success && let Some(failed) = results.last() {
+                    let chain = error_chain(&nodes_list, &results);
+                    notify_error_workflow(db.clone(), workflow.id, workflow.name.clone(), record.id, failed.node_name.clone(), failed.error.clone().unwrap_or_default(), chain).await;
+                }
             });
 
             return Ok(StatusCode::OK);
@@ -335,7 +356,21 @@ pub async fn execute_workflow(
     State(state): State<AppState>,
     Json(request): Json<ExecuteWorkflowRequest>,
 ) -> Result<Json<ExecuteWorkflowResponse>, StatusCode> {
-    let execution_id = Uuid::new_v4();
+    // A client-chosen id (usually from `reserve_execution`) must not belong to another execution
+    let execution_id = match request.execution_id {
+        Some(id) => {
+            let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM executions WHERE id = $1)")
+                .bind(id)
+                .fetch_one(&state.db)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if exists || !events::start(id) {
+                return Err(StatusCode::CONFLICT);
+            }
+            id
+        }
+        None => Uuid::new_v4(),
+    };
     let start_time = chrono::Utc::now();
     let mut results = Vec::new();
     let mut success = true;
@@ -346,6 +381,7 @@ pub async fn execute_workflow(
         .await
         .unwrap_or_default()
         .unwrap_or_else(|| "Manual Execution".to_string());
+    let mut ctx = ExecutionContext::new(execution_id, request.workflow_id.clone(), workflow_name.clone());
 
     // Use a queue for BFS-like execution to handle branching
     let mut execution_queue = std::collections::VecDeque::new();
@@ -357,7 +393,7 @@ pub async fn execute_workflow(
         }
     } else {
         let trigger_nodes: Vec<&Node> = request.nodes.iter()
-            .filter(|n| !request.edges.iter().any(|e| e.to == n.id))
+            .filter(|n| !request.edges.iter().any(|e| e.to == n.id) && !is_sub_node(n, &request.edges))
             .collect();
         for trigger in trigger_nodes {
             execution_queue.push_back((trigger, serde_json::json!({})));
@@ -366,8 +402,12 @@ pub async fn execute_workflow(
 
     while let Some((node, input_data)) = execution_queue.pop_front() {
         let node_start_time = std::time::Instant::now();
-        match execute_single_node(&state.db, node, &request.nodes, &request.edges, &input_data).await {
+        events::publish(execution_id, ExecutionEvent::NodeStarted { node_id: node.id.to_string(), node_name: node.label.clone() });
+        match execute_single_node(&state.db, node, &request.nodes, &request.edges, &input_data, &ctx).await {
             Ok(output) => {
+                let (output, mut details) = take_details(output);
+                details.usage = usage::finish_node(&state.db, &ctx, node).await;
+                ctx.record(node, &output);
                 // 1. Handle Filtering
                 if let Some(true) = output.get("__filtered").and_then(|v| v.as_bool()) {
                     results.push(NodeExecutionResult {
@@ -377,7 +417,9 @@ pub async fn execute_workflow(
                         output: Some(output),
                         error: None,
                         execution_time_ms: node_start_time.elapsed().as_millis() as u64,
+                        details,
                     });
+                    events::publish(execution_id, ExecutionEvent::NodeFinished { result: results[results.len() - 1].clone() });
                     continue; // Stop this branch
                 }
 
@@ -400,12 +442,17 @@ pub async fn execute_workflow(
                         status: "waiting".to_string(),
                         results: serde_json::to_value(&results).unwrap_or(serde_json::json!([])),
                         snapshot: Some(snapshot),
+                        input_tokens: 0,
+                        output_tokens: 0,
+                        cost_usd: 0.0,
                     };
 
                     let _ = sqlx::query(
                         "INSERT INTO executions (id, workflow_id, workflow_name, start_time, status, results, snapshot) VALUES ($1, $2, $3, $4, $5, $6, $7)"
                     )
                     .bind(record.id).bind(&record.workflow_id).bind(&record.workflow_name).bind(record.start_time).bind(&record.status).bind(&record.results).bind(record.snapshot).execute(&state.db).await;
+                    usage::update_execution(&state.db, execution_id).await;
+                    events::finish(execution_id, "waiting", &results);
 
                     return Ok(Json(ExecuteWorkflowResponse { success: true, execution_id, results, error: Some("Workflow paused".to_string()) }));
                 }
@@ -417,13 +464,15 @@ pub async fn execute_workflow(
                     output: Some(output.clone()),
                     error: None,
                     execution_time_ms: node_start_time.elapsed().as_millis() as u64,
+                    details,
                 });
+                events::publish(execution_id, ExecutionEvent::NodeFinished { result: results[results.len() - 1].clone() });
 
                 // 3. Determine Next Nodes based on port
                 let port = output.get("__port").and_then(|v| v.as_str());
                 
                 let next_edges: Vec<&Edge> = request.edges.iter()
-                    .filter(|e| e.from == node.id && (port.is_none() || e.from_port == port.map(|s| s.to_string())))
+                    .filter(|e| e.from == node.id && !is_sub_node_edge(e) && (port.is_none() || e.from_port == port.map(|s| s.to_string())))
                     .collect();
 
                 for edge in next_edges {
@@ -442,7 +491,9 @@ pub async fn execute_workflow(
                     output: None,
                     error: Some(e),
                     execution_time_ms: node_start_time.elapsed().as_millis() as u64,
+                    details: failure_details(&state.db, &ctx, node).await,
                 });
+                events::publish(execution_id, ExecutionEvent::NodeFinished { result: results[results.len() - 1].clone() });
                 break;
             }
         }
@@ -457,12 +508,22 @@ pub async fn execute_workflow(
         status: if success { "success".to_string() } else { "failed".to_string() },
         results: serde_json::to_value(&results).unwrap_or(serde_json::json!([])),
         snapshot: None,
+        input_tokens: 0,
+        output_tokens: 0,
+        cost_usd: 0.0,
     };
 
     let _ = sqlx::query(
         "INSERT INTO executions (id, workflow_id, workflow_name, start_time, end_time, status, results) VALUES ($1, $2, $3, $4, $5, $6, $7)"
     )
     .bind(record.id).bind(&record.workflow_id).bind(&record.workflow_name).bind(record.start_time).bind(record.end_time).bind(&record.status).bind(&record.results).execute(&state.db).await;
+    usage::update_execution(&state.db, execution_id).await;
+    events::finish(execution_id, &record.status, &results);
+
+    if !success && let (Ok(workflow_id), Some(failed)) = (Uuid::parse_str(&record.workflow_id), results.last()) {
+        let chain = error_chain(&request.nodes, &results);
+        tokio::spawn(notify_error_workflow(state.db.clone(), workflow_id, record.workflow_name.clone(), execution_id, failed.node_name.clone(), failed.error.clone().unwrap_or_default(), chain));
+    }
 
     Ok(Json(ExecuteWorkflowResponse { success, execution_id, results, error: if success { None } else { Some("Workflow execution failed".to_string()) } }))
 }
@@ -472,6 +533,72 @@ pub async fn list_executions(State(state): State<AppState>) -> Json<Vec<Executio
     Json(executions)
 }
 
+/// Streams the live events of an execution as server-sent events. Subscribe before starting the
+/// execution (pass the same `execution_id` to `/api/workflows/execute`) to receive all of them.
+/// Reserves an execution id to pass to `/api/workflows/execute`, so its events can be subscribed to first.
+pub async fn reserve_execution() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "id": events::reserve() }))
+}
+
+/// Streams the events of a reserved or running execution. A finished execution gets its `finished`
+/// event once; an unknown id is a 404.
+pub async fn execution_events(Path(id): Path<Uuid>, State(state): State<AppState>) -> Response {
+    if let Some(rx) = events::subscribe(id) {
+        return Sse::new(event_stream(rx)).keep_alive(KeepAlive::default()).into_response();
+    }
+
+    match execution_status(&state.db, id).await {
+        // Started by a trigger and nobody subscribed yet
+        Ok(Some(status)) if status == "running" => {
+            let rx = events::open(id);
+            // It may have finished between the two reads, and then nothing would close the channel
+            if let Ok(Some(status)) = execution_status(&state.db, id).await && status == "running" {
+                return Sse::new(event_stream(rx)).keep_alive(KeepAlive::default()).into_response();
+            }
+            events::discard(id);
+            finished_stream(&state.db, id).await
+        }
+        Ok(Some(_)) => finished_stream(&state.db, id).await,
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn execution_status(db: &sqlx::PgPool, id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar::<_, String>("SELECT status FROM executions WHERE id = $1").bind(id).fetch_optional(db).await
+}
+
+/// A stream holding only the `finished` event of an execution, rebuilt from its record.
+async fn finished_stream(db: &sqlx::PgPool, id: Uuid) -> Response {
+    let record = match sqlx::query_as::<_, ExecutionRecord>("SELECT * FROM executions WHERE id = $1").bind(id).fetch_optional(db).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let results: Vec<NodeExecutionResult> = serde_json::from_value(record.results).unwrap_or_default();
+    let event = sse_event(&events::finished(&record.status, &results));
+    Sse::new(futures_util::stream::iter([Ok::<_, std::convert::Infallible>(event)])).into_response()
+}
+
+fn sse_event(event: &ExecutionEvent) -> Event {
+    let name = serde_json::to_value(event).ok().and_then(|v| v.get("type").and_then(|t| t.as_str()).map(|t| t.to_string())).unwrap_or_default();
+    Event::default().event(name).json_data(event).unwrap_or_default()
+}
+
+fn event_stream(rx: tokio::sync::broadcast::Receiver<ExecutionEvent>) -> impl futures_util::Stream<Item = Result<Event, std::convert::Infallible>> {
+    use tokio::sync::broadcast::error::RecvError;
+    futures_util::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((Ok(sse_event(&event)), rx)),
+                // A slow client missed some tokens; keep going with the newest events
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
 pub async fn get_execution(Path(id): Path<Uuid>, State(state): State<AppState>) -> Result<Json<ExecutionRecord>, StatusCode> {
     let record = sqlx::query_as::<_, ExecutionRecord>("SELECT * FROM executions WHERE id = $1").bind(id).fetch_optional(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
     Ok(Json(record))
@@ -484,9 +611,14 @@ pub async fn execute_node_handler(State(state): State<AppState>, Json(request):
     let node = &request.node;
     let node_start_time = std::time::Instant::now();
 
-    let result = match execute_single_node(&state.db, node, &vec![], &vec![], &serde_json::json!({})).await {
-        Ok(output) => NodeExecutionResult { node_id: node.id.to_string(), node_name: node.label.clone(), success: true, output: Some(output), error: None, execution_time_ms: node_start_time.elapsed().as_millis() as u64 },
-        Err(e) => NodeExecutionResult { node_id: node.id.to_string(), node_name: node.label.clone(), success: false, output: None, error: Some(e), execution_time_ms: node_start_time.elapsed().as_millis() as u64 },
+    let ctx = ExecutionContext::new(Uuid::new_v4(), "", "Manual Execution");
+    let result = match execute_single_node(&state.db, node, &vec![], &vec![], &serde_json::json!({}), &ctx).await {
+        Ok(output) => {
+            let (output, mut details) = take_details(output);
+            details.usage = usage::finish_node(&state.db, &ctx, node).await;
+            NodeExecutionResult { node_id: node.id.to_string(), node_name: node.label.clone(), success: true, output: Some(output), error: None, execution_time_ms: node_start_time.elapsed().as_millis() as u64, details }
+        }
+        Err(e) => NodeExecutionResult { node_id: node.id.to_string(), node_name: node.label.clone(), success: false, output: None, error: Some(e), execution_time_ms: node_start_time.elapsed().as_millis() as u64, details: failure_details(&state.db, &ctx, node).await },
     };
 
     Ok(Json(result))
@@ -509,17 +641,113 @@ pub async fn create_workflow(State(state): State<AppState>, Json(input): Json<Wo
         description: input.description,
         nodes: serde_json::to_value(input.nodes).unwrap_or(serde_json::json!([])),
         edges: serde_json::to_value(input.edges).unwrap_or(serde_json::json!([])),
+        active: false,
+        settings: input.settings.unwrap_or(serde_json::json!({})),
     };
-    let _ = sqlx::query("INSERT INTO workflows (id, name, description, nodes, edges) VALUES ($1, $2, $3, $4, $5)").bind(workflow.id).bind(&workflow.name).bind(&workflow.description).bind(&workflow.nodes).bind(&workflow.edges).execute(&state.db).await;
+    let _ = sqlx::query("INSERT INTO workflows (id, name, description, nodes, edges, settings) VALUES ($1, $2, $3, $4, $5, $6)").bind(workflow.id).bind(&workflow.name).bind(&workflow.description).bind(&workflow.nodes).bind(&workflow.edges).bind(&workflow.settings).execute(&state.db).await;
     Json(workflow)
 }
 
-pub async fn update_workflow(Path(id): Path<Uuid>, State(state): State<AppState>, Json(input): Json<WorkflowInput>) -> Result<Json<Workflow>, StatusCode> {
-    let nodes = serde_json::to_value(input.nodes).unwrap_or(serde_json::json!([]));
-    let edges = serde_json::to_value(input.edges).unwrap_or(serde_json::json!([]));
-    let result = sqlx::query("UPDATE workflows SET name = $1, description = $2, nodes = $3, edges = $4, updated_at = NOW() WHERE id = $5").bind(&input.name).bind(&input.description).bind(&nodes).bind(&edges).bind(id).execute(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    if result.rows_affected() == 0 { return Err(StatusCode::NOT_FOUND); }
-    Ok(Json(Workflow { id, name: input.name, description: input.description, nodes, edges }))
+pub async fn update_workflow(Path(id): Path<Uuid>, State(state): State<AppState>, Json(input): Json<WorkflowInput>) -> Response {
+    let current = match sqlx::query_as::<_, Workflow>("SELECT * FROM workflows WHERE id = $1").bind(id).fetch_optional(&state.db).await {
+        Ok(Some(w)) => w,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let workflow = Workflow {
+        id,
+        name: input.name,
+        description: input.description,
+        nodes: serde_json::to_value(input.nodes).unwrap_or(serde_json::json!([])),
+        edges: serde_json::to_value(input.edges).unwrap_or(serde_json::json!([])),
+        active: current.active,
+        settings: input.settings.unwrap_or(current.settings),
+    };
+
+    // An active workflow's new triggers go live on save, so they must be valid and not take another workflow's paths
+    if workflow.active {
+        let errors = state.triggers.validate_workflow(&workflow);
+        if !errors.is_empty() {
+            return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "Workflow has invalid trigger configuration", "errors": errors }))).into_response();
+        }
+    }
+
+    if sqlx::query("UPDATE workflows SET name = $1, description = $2, nodes = $3, edges = $4, settings = $5, updated_at = NOW() WHERE id = $6").bind(&workflow.name).bind(&workflow.description).bind(&workflow.nodes).bind(&workflow.edges).bind(&workflow.settings).bind(id).execute(&state.db).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let conflicts = state.triggers.sync_workflow(&state.db, &workflow);
+    if !conflicts.is_empty() {
+        state.triggers.stop_workflow(id);
+        let _ = sqlx::query("UPDATE workflows SET active = FALSE WHERE id = $1").bind(id).execute(&state.db).await;
+        return (StatusCode::CONFLICT, Json(serde_json::json!({ "error": "Workflow was saved but deactivated", "errors": conflicts }))).into_response();
+    }
+    Json(workflow).into_response()
+}
+
+pub async fn activate_workflow(Path(id): Path<Uuid>, State(state): State<AppState>) -> Response {
+    let workflow = match sqlx::query_as::<_, Workflow>("SELECT * FROM workflows WHERE id = $1").bind(id).fetch_optional(&state.db).await {
+        Ok(Some(w)) => w,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let errors = state.triggers.validate_workflow(&workflow);
+    if !errors.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "Workflow has invalid trigger configuration", "errors": errors }))).into_response();
+    }
+
+    if sqlx::query("UPDATE workflows SET active = TRUE, updated_at = NOW() WHERE id = $1").bind(id).execute(&state.db).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let workflow = Workflow { active: true, ..workflow };
+    let conflicts = state.triggers.sync_workflow(&state.db, &workflow);
+    if !conflicts.is_empty() {
+        // Another workflow claimed a path between validation and registration
+        state.triggers.stop_workflow(id);
+        let _ = sqlx::query("UPDATE workflows SET active = FALSE WHERE id = $1").bind(id).execute(&state.db).await;
+        return (StatusCode::CONFLICT, Json(serde_json::json!({ "error": "Workflow has invalid trigger configuration", "errors": conflicts }))).into_response();
+    }
+    Json(workflow).into_response()
+}
+
+pub async fn deactivate_workflow(Path(id): Path<Uuid>, State(state): State<AppState>) -> Result<Json<Workflow>, StatusCode> {
+    let workflow = sqlx::query_as::<_, Workflow>("UPDATE workflows SET active = FALSE, updated_at = NOW() WHERE id = $1 RETURNING *").bind(id).fetch_optional(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+    state.triggers.stop_workflow(id);
+    Ok(Json(workflow))
+}
+
+pub async fn handle_webhook(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(ctx) = state.triggers.webhook(&path) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": format!("No active webhook registered for '{}'", path) }))).into_response();
+    };
+
+    let expected = ctx.node.config.get("method").and_then(|v| v.as_str()).unwrap_or("POST");
+    if !expected.eq_ignore_ascii_case(method.as_str()) {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    let body_text = String::from_utf8_lossy(&body).to_string();
+    let body_json = serde_json::from_str::<serde_json::Value>(&body_text).unwrap_or(serde_json::json!(body_text));
+    let header_map: serde_json::Map<String, serde_json::Value> = headers.iter()
+        .map(|(k, v)| (k.to_string(), serde_json::json!(v.to_str().unwrap_or(""))))
+        .collect();
+    let event = serde_json::json!({
+        "method": method.as_str(),
+        "path": path,
+        "headers": header_map,
+        "query": query,
+        "body": body_json,
+    });
+
+    tokio::spawn(async move { ctx.emit(event).await; });
+    Json(serde_json::json!({ "message": "Workflow was started" })).into_response()
 }
 
 pub async fn list_credentials(State(state): State<AppState>) -> Json<Vec<Credential>> {
@@ -723,52 +951,74 @@ pub struct LlmExecuteRequest { pub prompt: String, pub model: String }
 #[derive(Debug, Serialize)]
 pub struct LlmExecuteResponse { pub success: bool, pub output: Option<serde_json::Value>, pub error: Option<String> }
 
-pub async fn execute_llm(State(state): State<AppState>, Json(request): Json<LlmExecuteRequest>) -> Result<Json<LlmExecuteResponse>, StatusCode> {
-    let Some(openrouter) = &state.openrouter else {
-        return Ok(Json(LlmExecuteResponse { success: false, output: None, error: Some("OpenRouter API key not configured".to_string()) }));
-    };
-    let messages = vec![OpenRouterMessage { 
-        role: "user".to_string(), 
-        content: request.prompt,
-        tool_calls: None,
-        tool_call_id: None
-    }];
-    let or_request = OpenRouterRequest { 
-        model: request.model, 
-        messages, 
-        temperature: None, 
-        max_tokens: None, 
-        top_p: None, 
-        frequency_penalty: None, 
-        presence_penalty: None, 
-        response_format: None,
-        tools: None,
-        tool_choice: None,
-    };
-    match openrouter.generate(or_request).await {
-        Ok(res) => Ok(Json(LlmExecuteResponse { success: true, output: Some(res), error: None })),
-        Err(e) => Ok(Json(LlmExecuteResponse { success: false, output: None, error: Some(format!("OpenRouter API error: {}", e)) })),
-    }
+pub async fn execute_llm(State(_state): State<AppState>, Json(request): Json<LlmExecuteRequest>) -> Result<Json<LlmExecuteResponse>, StatusCode> {
+    run_llm("openrouter", "OpenRouter", request).await
+}
+
+pub async fn execute_openai(State(_state): State<AppState>, Json(request): Json<LlmExecuteRequest>) -> Result<Json<LlmExecuteResponse>, StatusCode> {
+    run_llm("openai", "OpenAI", request).await
 }
 
-pub async fn execute_openai(State(state): State<AppState>, Json(request): Json<LlmExecuteRequest>) -> Result<Json<LlmExecuteResponse>, StatusCode> {
-    let Some(openai) = &state.openai else {
-        return Ok(Json(LlmExecuteResponse { success: false, output: None, error: Some("OpenAI API key not configured".to_string()) }));
+async fn run_llm(provider: &str, label: &str, request: LlmExecuteRequest) -> Result<Json<LlmExecuteResponse>, StatusCode> {
+    let Ok(client) = registry().create(provider, ProviderSettings::default()) else {
+        return Ok(Json(LlmExecuteResponse { success: false, output: None, error: Some(format!("{} API key not configured", label)) }));
     };
-    let messages = vec![OpenAiMessage { 
-        role: "user".to_string(), 
-        content: request.prompt,
-        tool_calls: None,
-        tool_call_id: None
-    }];
-    match openai.generate(&request.model, messages, None, None, None).await {
-        Ok(res) => Ok(Json(LlmExecuteResponse { success: true, output: Some(res), error: None })),
-        Err(e) => Ok(Json(LlmExecuteResponse { success: false, output: None, error: Some(format!("OpenAI API error: {}", e)) })),
+    let chat_request = ChatRequest { model: request.model, messages: vec![ChatMessage::user(request.prompt)], ..Default::default() };
+    match client.chat(chat_request).await {
+        Ok(res) => Ok(Json(LlmExecuteResponse { success: true, output: Some(res.raw), error: None })),
+        Err(e) => Ok(Json(LlmExecuteResponse { success: false, output: None, error: Some(e) })),
     }
 }
 
-pub async fn chat_trigger(State(_state): State<AppState>, Json(_input): Json<serde_json::Value>) -> Result<Json<serde_json::Value>, StatusCode> {
-    Ok(Json(serde_json::json!({ "status": "chat triggered" })))
+#[derive(Debug, Deserialize)]
+pub struct ChatTriggerRequest {
+    pub workflow_id: Uuid,
+    pub message: String,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default = "default_true")]
+    pub stream: bool,
+}
+
+fn default_true() -> bool { true }
+
+/// Sends a chat message to a saved workflow through its `chat-trigger` node. With `stream` (the default)
+/// the response is the execution's event stream, so tokens arrive as they are generated and the final
+/// `finished` event carries the workflow's answer. Otherwise the answer is returned once the run completes.
+pub async fn chat_trigger(State(state): State<AppState>, Json(request): Json<ChatTriggerRequest>) -> Response {
+    let workflow = match sqlx::query_as::<_, Workflow>("SELECT * FROM workflows WHERE id = $1").bind(request.workflow_id).fetch_optional(&state.db).await {
+        Ok(Some(workflow)) => workflow,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let nodes: Vec<Node> = serde_json::from_value(workflow.nodes.clone()).unwrap_or_default();
+    let edges: Vec<Edge> = serde_json::from_value(workflow.edges.clone()).unwrap_or_default();
+    let Some(trigger) = nodes.iter().find(|n| n.kind == "chat-trigger").cloned() else {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "Workflow has no Chat Trigger node" }))).into_response();
+    };
+
+    let execution_id = Uuid::new_v4();
+    let session_id = request.session_id.unwrap_or_else(|| execution_id.to_string());
+    let input = serde_json::json!({ "message": request.message, "sessionId": session_id });
+    let rx = request.stream.then(|| events::open(execution_id));
+
+    let run = {
+        let db = state.db.clone();
+        async move { run_workflow_execution(&db, execution_id, workflow.id, &workflow.name, &nodes, &edges, &trigger, input).await }
+    };
+    match rx {
+        Some(rx) => {
+            tokio::spawn(run);
+            Sse::new(event_stream(rx)).keep_alive(KeepAlive::default()).into_response()
+        }
+        None => {
+            let results = run.await;
+            let success = results.iter().all(|r| r.success);
+            let output = results.iter().rev().find(|r| r.success).and_then(|r| r.output.clone());
+            let error = results.iter().find_map(|r| r.error.clone());
+            Json(serde_json::json!({ "execution_id": execution_id, "session_id": session_id, "success": success, "output": output, "error": error })).into_response()
+        }
+    }
 }
 
 pub async fn execute_http_request(State(_state): State<AppState>, Json(request): Json<HttpRequestExecuteRequest>) -> Result<Json<HttpRequestExecuteResponse>, StatusCode> {
@@ -805,6 +1055,9 @@ pub struct ExecuteWorkflowRequest {
     pub nodes: Vec<Node>, 
     pub edges: Vec<Edge>,
     pub trigger_node_id: Option<Uuid>,
+    /// Lets the client subscribe to `/api/executions/:id/events` before the execution starts.
+    #[serde(default)]
+    pub execution_id: Option<Uuid>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -902,3 +1155,381 @@ pub async fn update_data_table_schema(
         .fetch_one(&state.db).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(table))
 }
+
+// Knowledge Base Handlers
+pub async fn list_knowledge_bases(State(state): State<AppState>) -> Json<Vec<KnowledgeBase>> {
+    let bases = sqlx::query_as::<_, KnowledgeBase>("SELECT * FROM knowledge_bases ORDER BY name ASC")
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+    Json(bases)
+}
+
+/// Checks the settings a knowledge base is created or updated with.
+fn validate_knowledge_base(kb: &KnowledgeBase) -> Result<(), String> {
+    if kb.name.trim().is_empty() {
+        return Err("Name is required".to_string());
+    }
+    if !registry().contains(&kb.embedding_provider) {
+        return Err(format!("Unknown LLM provider: {}", kb.embedding_provider));
+    }
+    if kb.chunk_size < 1 {
+        return Err("Chunk size must be at least 1".to_string());
+    }
+    if kb.chunk_overlap < 0 || kb.chunk_overlap >= kb.chunk_size {
+        return Err("Chunk overlap must be at least 0 and smaller than the chunk size".to_string());
+    }
+    Ok(())
+}
+
+fn knowledge_base_error(e: sqlx::Error) -> Response {
+    match e.as_database_error().and_then(|d| d.code()) {
+        Some(code) if code == "23505" => (StatusCode::CONFLICT, Json(serde_json::json!({ "error": "A knowledge base with this name already exists" }))).into_response(),
+        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn create_knowledge_base(State(state): State<AppState>, Json(input): Json<KnowledgeBaseInput>) -> Response {
+    let kb = KnowledgeBase {
+        id: Uuid::new_v4(),
+        name: input.name,
+        description: input.description,
+        embedding_provider: input.embedding_provider.filter(|s| !s.is_empty()).unwrap_or_else(|| knowledge::DEFAULT_PROVIDER.to_string()),
+        embedding_model: input.embedding_model.filter(|s| !s.is_empty()).unwrap_or_else(|| knowledge::DEFAULT_MODEL.to_string()),
+        dimensions: input.dimensions.filter(|d| *d > 0),
+        chunk_size: input.chunk_size.unwrap_or(knowledge::DEFAULT_CHUNK_SIZE),
+        chunk_overlap: input.chunk_overlap.unwrap_or(knowledge::DEFAULT_CHUNK_OVERLAP),
+        credential_id: input.credential_id,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+    };
+    if let Err(e) = validate_knowledge_base(&kb) {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response();
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO knowledge_bases (id, name, description, embedding_provider, embedding_model, dimensions, chunk_size, chunk_overlap, credential_id, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+    )
+    .bind(kb.id).bind(&kb.name).bind(&kb.description).bind(&kb.embedding_provider).bind(&kb.embedding_model).bind(kb.dimensions)
+    .bind(kb.chunk_size).bind(kb.chunk_overlap).bind(kb.credential_id).bind(kb.created_at).bind(kb.updated_at)
+    .execute(&state.db).await;
+
+    match result {
+        Ok(_) => Json(kb).into_response(),
+        Err(e) => knowledge_base_error(e),
+    }
+}
+
+/// Updates name, description, credential and chunking. New chunking applies to documents uploaded afterwards.
+/// The embedding provider, model and dimensions stay fixed so all vectors of a knowledge base remain comparable.
+pub async fn update_knowledge_base(Path(id): Path<Uuid>, State(state): State<AppState>, Json(input): Json<KnowledgeBaseInput>) -> Response {
+    let Ok(current) = knowledge::load(&state.db, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let kb = KnowledgeBase {
+        name: input.name,
+        description: input.description,
+        chunk_size: input.chunk_size.unwrap_or(current.chunk_size),
+        chunk_overlap: input.chunk_overlap.unwrap_or(current.chunk_overlap),
+        credential_id: input.credential_id.or(current.credential_id),
+        ..current
+    };
+    if let Err(e) = validate_knowledge_base(&kb) {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response();
+    }
+
+    let result = sqlx::query_as::<_, KnowledgeBase>(
+        "UPDATE knowledge_bases SET name = $1, description = $2, chunk_size = $3, chunk_overlap = $4, credential_id = $5, updated_at = NOW()
+         WHERE id = $6 RETURNING *"
+    )
+    .bind(&kb.name).bind(&kb.description).bind(kb.chunk_size).bind(kb.chunk_overlap).bind(kb.credential_id).bind(id)
+    .fetch_one(&state.db).await;
+
+    match result {
+        Ok(kb) => Json(kb).into_response(),
+        Err(e) => knowledge_base_error(e),
+    }
+}
+
+pub async fn delete_knowledge_base(Path(id): Path<Uuid>, State(state): State<AppState>) -> StatusCode {
+    let _ = sqlx::query("DELETE FROM knowledge_bases WHERE id = $1").bind(id).execute(&state.db).await;
+    StatusCode::OK
+}
+
+pub async fn list_knowledge_documents(Path(id): Path<Uuid>, State(state): State<AppState>) -> Json<Vec<KnowledgeDocument>> {
+    let documents = sqlx::query_as::<_, KnowledgeDocument>("SELECT * FROM knowledge_documents WHERE knowledge_base_id = $1 ORDER BY created_at DESC")
+        .bind(id)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+    Json(documents)
+}
+
+/// Stores the uploaded files as `processing` documents and returns them. Text extraction, chunking and
+/// embedding run in the background; poll the document list for the outcome.
+pub async fn upload_knowledge_documents(Path(id): Path<Uuid>, State(state): State<AppState>, mut multipart: Multipart) -> Response {
+    let Ok(kb) = knowledge::load(&state.db, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let mut uploads = Vec::new();
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) => {
+                let Some(file_name) = field.file_name().map(|s| s.to_string()).filter(|s| !s.is_empty()) else { continue; };
+                let mime_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+                match field.bytes().await {
+                    Ok(data) => uploads.push((file_name, mime_type, data.to_vec())),
+                    Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e.to_string() }))).into_response(),
+                }
+            }
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e.to_string() }))).into_response(),
+        }
+    }
+    if uploads.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "No files uploaded" }))).into_response();
+    }
+
+    let mut documents = Vec::new();
+    for (file_name, mime_type, data) in uploads {
+        let document = KnowledgeDocument {
+            id: Uuid::new_v4(),
+            knowledge_base_id: kb.id,
+            file_name,
+            mime_type,
+            size: data.len() as i64,
+            status: "processing".to_string(),
+            error: None,
+            chunk_count: 0,
+            created_at: chrono::Utc::now(),
+        };
+        let inserted = sqlx::query("INSERT INTO knowledge_documents (id, knowledge_base_id, file_name, mime_type, size, status, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+            .bind(document.id).bind(document.knowledge_base_id).bind(&document.file_name).bind(&document.mime_type).bind(document.size).bind(&document.status).bind(document.created_at)
+            .execute(&state.db).await;
+        if inserted.is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        tokio::spawn(knowledge::ingest_document(state.db.clone(), kb.clone(), document.id, document.file_name.clone(), document.mime_type.clone(), data));
+        documents.push(document);
+    }
+    Json(documents).into_response()
+}
+
+pub async fn delete_knowledge_document(Path((kb_id, document_id)): Path<(Uuid, Uuid)>, State(state): State<AppState>) -> StatusCode {
+    let _ = sqlx::query("DELETE FROM knowledge_documents WHERE id = $1 AND knowledge_base_id = $2")
+        .bind(document_id).bind(kb_id)
+        .execute(&state.db).await;
+    StatusCode::OK
+}
+
+pub async fn search_knowledge_base(Path(id): Path<Uuid>, State(state): State<AppState>, Json(input): Json<KnowledgeSearchInput>) -> Response {
+    let Ok(kb) = knowledge::load(&state.db, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let top_k = input.top_k.filter(|k| *k > 0).unwrap_or(knowledge::DEFAULT_TOP_K);
+    match knowledge::search(&state.db, &kb, &input.query, top_k).await {
+        Ok((results, usage)) => {
+            if let Some(usage) = usage {
+                usage::record_knowledge_base(&state.db, &kb, "search", "Search", &usage).await;
+            }
+            Json(results).into_response()
+        }
+        Err(e) => (StatusCode::BAD_GATEWAY, Json(serde_json::json!({ "error": e }))).into_response(),
+    }
+}
+
+// Code Module Handlers
+const CODE_MODULE_LANGUAGES: [&str; 2] = ["javascript", "python"];
+
+/// Module names double as JS import specifiers and Python module names, so they must be identifiers.
+fn validate_code_module(input: &CodeModuleInput) -> Result<(), String> {
+    let mut chars = input.name.chars();
+    let valid_name = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Err("Module name must start with a letter or underscore and contain only letters, digits and underscores".to_string());
+    }
+    if input.name == "user_code" {
+        return Err("Module name 'user_code' is reserved".to_string());
+    }
+    if !CODE_MODULE_LANGUAGES.contains(&input.language.as_str()) {
+        return Err(format!("Unsupported language: {}", input.language));
+    }
+    Ok(())
+}
+
+fn code_module_error(e: sqlx::Error) -> Response {
+    match e.as_database_error().and_then(|d| d.code()) {
+        Some(code) if code == "23505" => (StatusCode::CONFLICT, Json(serde_json::json!({ "error": "A module with this name already exists for the language" }))).into_response(),
+        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn list_code_modules(State(state): State<AppState>) -> Json<Vec<CodeModule>> {
+    let modules = sqlx::query_as::<_, CodeModule>("SELECT * FROM code_modules ORDER BY name ASC, language ASC")
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+    Json(modules)
+}
+
+pub async fn get_code_module(Path(id): Path<Uuid>, State(state): State<AppState>) -> Result<Json<CodeModule>, StatusCode> {
+    let module = sqlx::query_as::<_, CodeModule>("SELECT * FROM code_modules WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(module))
+}
+
+pub async fn create_code_module(State(state): State<AppState>, Json(input): Json<CodeModuleInput>) -> Response {
+    if let Err(e) = validate_code_module(&input) {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response();
+    }
+
+    let module = CodeModule {
+        id: Uuid::new_v4(),
+        name: input.name,
+        language: input.language,
+        description: input.description,
+        version: 1,
+        code: input.code,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+    };
+
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        sqlx::query("INSERT INTO code_modules (id, name, language, description, version, code, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+            .bind(module.id).bind(&module.name).bind(&module.language).bind(&module.description).bind(module.version).bind(&module.code).bind(module.created_at).bind(module.updated_at)
+            .execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO code_module_versions (module_id, version, code, created_at) VALUES ($1, $2, $3, $4)")
+            .bind(module.id).bind(module.version).bind(&module.code).bind(module.created_at)
+            .execute(&mut *tx).await?;
+        tx.commit().await
+    }.await;
+
+    match result {
+        Ok(()) => Json(module).into_response(),
+        Err(e) => code_module_error(e),
+    }
+}
+
+/// Saves a module. Changing the code publishes a new version; nodes pinned to an older one keep using it.
+pub async fn update_code_module(Path(id): Path<Uuid>, State(state): State<AppState>, Json(input): Json<CodeModuleInput>) -> Response {
+    if let Err(e) = validate_code_module(&input) {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response();
+    }
+
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        let Some(current) = sqlx::query_as::<_, CodeModule>("SELECT * FROM code_modules WHERE id = $1 FOR UPDATE").bind(id).fetch_optional(&mut *tx).await? else {
+            return Ok(None);
+        };
+
+        let version = if current.code == input.code { current.version } else { current.version + 1 };
+        let module = sqlx::query_as::<_, CodeModule>("UPDATE code_modules SET name = $1, language = $2, description = $3, version = $4, code = $5, updated_at = NOW() WHERE id = $6 RETURNING *")
+            .bind(&input.name).bind(&input.language).bind(&input.description).bind(version).bind(&input.code).bind(id)
+            .fetch_one(&mut *tx).await?;
+        if version != current.version {
+            sqlx::query("INSERT INTO code_module_versions (module_id, version, code) VALUES ($1, $2, $3)")
+                .bind(id).bind(version).bind(&input.code)
+                .execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(Some(module))
+    }.await;
+
+    match result {
+        Ok(Some(module)) => Json(module).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => code_module_error(e),
+    }
+}
+
+pub async fn delete_code_module(Path(id): Path<Uuid>, State(state): State<AppState>) -> StatusCode {
+    let _ = sqlx::query("DELETE FROM code_modules WHERE id = $1").bind(id).execute(&state.db).await;
+    StatusCode::OK
+}
+
+pub async fn list_code_module_versions(Path(id): Path<Uuid>, State(state): State<AppState>) -> Json<Vec<CodeModuleVersion>> {
+    let versions = sqlx::query_as::<_, CodeModuleVersion>("SELECT * FROM code_module_versions WHERE module_id = $1 ORDER BY version DESC")
+        .bind(id)
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+    Json(versions)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// First day to include (UTC).
+    pub from: Option<chrono::NaiveDate>,
+    /// Last day to include (UTC).
+    pub to: Option<chrono::NaiveDate>,
+    pub workflow_id: Option<String>,
+}
+
+/// LLM token usage and cost per workflow, model and day.
+pub async fn get_usage(Query(query): Query<UsageQuery>, State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    let rows = sqlx::query_as::<_, UsageSummary>(
+        "SELECT workflow_id, MAX(workflow_name) AS workflow_name, model, (created_at AT TIME ZONE 'UTC')::DATE AS day,
+                SUM(calls)::BIGINT AS calls, SUM(input_tokens)::BIGINT AS input_tokens, SUM(output_tokens)::BIGINT AS output_tokens,
+                SUM(cost_usd) AS cost_usd
+         FROM llm_usage
+         WHERE ($1::DATE IS NULL OR (created_at AT TIME ZONE 'UTC')::DATE >= $1)
+           AND ($2::DATE IS NULL OR (created_at AT TIME ZONE 'UTC')::DATE <= $2)
+           AND ($3::TEXT IS NULL OR workflow_id = $3)
+         GROUP BY workflow_id, model, day
+         ORDER BY day DESC, workflow_id, model"
+    )
+    .bind(query.from)
+    .bind(query.to)
+    .bind(query.workflow_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let totals = serde_json::json!({
+        "calls": rows.iter().map(|r| r.calls).sum::<i64>(),
+        "input_tokens": rows.iter().map(|r| r.input_tokens).sum::<i64>(),
+        "output_tokens": rows.iter().map(|r| r.output_tokens).sum::<i64>(),
+        "cost_usd": rows.iter().filter_map(|r| r.cost_usd).sum::<f64>(),
+    });
+    Ok(Json(serde_json::json!({ "totals": totals, "rows": rows })))
+}
+
+pub async fn list_model_prices(State(state): State<AppState>) -> Json<Vec<ModelPrice>> {
+    let prices = sqlx::query_as::<_, ModelPrice>("SELECT * FROM model_prices ORDER BY model ASC")
+        .fetch_all(&state.db)
+        .await
+        .unwrap_or_default();
+    Json(prices)
+}
+
+/// Adds or changes the price of a model. Only calls made afterwards use the new price.
+pub async fn upsert_model_price(State(state): State<AppState>, Json(price): Json<ModelPrice>) -> Result<Json<ModelPrice>, StatusCode> {
+    if price.model.trim().is_empty() || price.input_per_million < 0.0 || price.output_per_million < 0.0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let price = sqlx::query_as::<_, ModelPrice>(
+        "INSERT INTO model_prices (model, input_per_million, output_per_million) VALUES ($1, $2, $3)
+         ON CONFLICT (model) DO UPDATE SET input_per_million = EXCLUDED.input_per_million, output_per_million = EXCLUDED.output_per_million
+         RETURNING *"
+    )
+    .bind(price.model.trim())
+    .bind(price.input_per_million)
+    .bind(price.output_per_million)
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(price))
+}
+
+pub async fn delete_model_price(Path(model): Path<String>, State(state): State<AppState>) -> StatusCode {
+    let _ = sqlx::query("DELETE FROM model_prices WHERE model = $1").bind(model).execute(&state.db).await;
+    StatusCode::OK
+}
@@ -4,24 +4,24 @@ mod db;
 mod handlers;
 mod clients;
 mod engine;
+mod triggers;
 
 use axum::{
-    routing::{get, post, delete, put},
+    extract::DefaultBodyLimit,
+    routing::{any, get, post, delete, put},
     Router,
 };
 use std::net::SocketAddr;
 use tower_http::cors::{Any, CorsLayer};
 use sqlx::postgres::PgPoolOptions;
 use crate::state::AppState;
-use crate::clients::{OpenAiClient, OpenRouterClient};
+use crate::models::Workflow;
+use crate::triggers::TriggerManager;
 use crate::handlers::*;
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
-    
-    let openrouter = std::env::var("OPENROUTER_API_KEY").ok().map(OpenRouterClient::new);
-    let openai = std::env::var("OPENAI_API_KEY").ok().map(OpenAiClient::new);
 
     let database_url = std::env::var("DATABASE_URL")
         .unwrap_or_else(|_| "postgres://nexus:nexus@db:5432/nexus".to_string());
@@ -33,11 +33,22 @@ async fn main() {
         .expect("Failed to connect to Postgres");
 
     db::init_db(&pool).await.expect("Failed to initialize database");
+    engine::cache::spawn_purge(pool.clone());
+
+    let triggers = TriggerManager::default();
+    let workflows = sqlx::query_as::<_, Workflow>("SELECT * FROM workflows WHERE active = TRUE")
+        .fetch_all(&pool)
+        .await
+        .unwrap_or_default();
+    for workflow in &workflows {
+        for conflict in triggers.sync_workflow(&pool, workflow) {
+            eprintln!("Workflow '{}' trigger not registered: {}", workflow.name, conflict);
+        }
+    }
 
     let state = AppState {
         db: pool,
-        openrouter,
-        openai,
+        triggers,
     };
 
     let cors = CorsLayer::new()
@@ -49,6 +60,8 @@ async fn main() {
         .route("/api/health", get(health))
         .route("/api/workflows", get(list_workflows).post(create_workflow))
         .route("/api/workflows/:id", get(get_workflow).put(update_workflow))
+        .route("/api/workflows/:id/activate", post(activate_workflow))
+        .route("/api/workflows/:id/deactivate", post(deactivate_workflow))
         .route("/api/mcp/servers", get(list_mcp_servers).post(create_mcp_server))
         .route("/api/mcp/servers/:id/tools", get(list_mcp_tools))
         .route("/api/mcp/servers/:id/status", get(check_mcp_server_status))
@@ -64,15 +77,29 @@ async fn main() {
         .route("/api/workflows/:id/export/n8n", get(export_workflow_n8n))
         .route("/api/workflows/current/export/n8n", get(export_current_workflow_n8n))
         .route("/api/mcp/servers/:id", delete(delete_mcp_server))
-        .route("/api/executions", get(list_executions))
+        .route("/api/executions", get(list_executions).post(reserve_execution))
         .route("/api/executions/:id", get(get_execution))
+        .route("/api/executions/:id/events", get(execution_events))
+        .route("/api/usage", get(get_usage))
+        .route("/api/usage/prices", get(list_model_prices).put(upsert_model_price))
+        .route("/api/usage/prices/*model", delete(delete_model_price))
         .route("/api/webhooks/slack/interactive", post(handle_slack_interactive))
         .route("/api/webhooks/slack/events", post(handle_slack_events))
+        .route("/webhook/*path", any(handle_webhook))
+        .route("/form/*path", get(show_form).post(submit_form))
         .route("/api/data-tables", get(list_data_tables).post(create_data_table))
         .route("/api/data-tables/:id", delete(delete_data_table))
         .route("/api/data-tables/:id/schema", put(update_data_table_schema))
         .route("/api/data-tables/:id/rows", get(get_data_table_rows).post(add_data_table_row))
         .route("/api/data-tables/:table_id/rows/:row_id", put(update_data_table_row).delete(delete_data_table_row))
+        .route("/api/knowledge-bases", get(list_knowledge_bases).post(create_knowledge_base))
+        .route("/api/knowledge-bases/:id", put(update_knowledge_base).delete(delete_knowledge_base))
+        .route("/api/knowledge-bases/:id/documents", get(list_knowledge_documents).post(upload_knowledge_documents).layer(DefaultBodyLimit::max(50 * 1024 * 1024)))
+        .route("/api/knowledge-bases/:kb_id/documents/:document_id", delete(delete_knowledge_document))
+        .route("/api/knowledge-bases/:id/search", post(search_knowledge_base))
+        .route("/api/code-modules", get(list_code_modules).post(create_code_module))
+        .route("/api/code-modules/:id", get(get_code_module).put(update_code_module).delete(delete_code_module))
+        .route("/api/code-modules/:id/versions", get(list_code_module_versions))
         .layer(cors)
         .with_state(state);
 
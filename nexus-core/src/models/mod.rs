@@ -21,6 +21,13 @@ pub struct ExecutionRecord {
     pub status: String,
     pub results: serde_json::Value,
     pub snapshot: Option<serde_json::Value>,
+    /// LLM tokens used by all nodes of the execution, and what they cost.
+    #[serde(default)]
+    pub input_tokens: i64,
+    #[serde(default)]
+    pub output_tokens: i64,
+    #[serde(default)]
+    pub cost_usd: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +38,82 @@ pub struct NodeExecutionResult {
     pub output: Option<serde_json::Value>,
     pub error: Option<String>,
     pub execution_time_ms: u64,
+    #[serde(flatten)]
+    pub details: NodeRunDetails,
+}
+
+/// Side data a node reports next to its output, serialized inline on `NodeExecutionResult`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeRunDetails {
+    /// Console output captured from Code nodes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<serde_json::Value>,
+    /// Shared code modules a Code node imported, with the version that was loaded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modules: Vec<CodeModuleRef>,
+    /// Tokens the LLM calls of the node used, and what they cost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<NodeUsage>,
+    /// Model turns of an agent that failed before answering; a successful agent returns them in its output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<serde_json::Value>,
+}
+
+impl NodeRunDetails {
+    pub fn is_empty(&self) -> bool {
+        self.logs.is_empty() && self.modules.is_empty() && self.usage.is_none() && self.trace.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Cost of the calls whose model has a price; see `model_prices`.
+    pub cost_usd: f64,
+    pub models: Vec<ModelUsage>,
+    /// Calls answered from the LLM response cache instead of the provider.
+    #[serde(default)]
+    pub cache_hits: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub provider: String,
+    pub model: String,
+    pub calls: u32,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// `None` when the model has no entry in the price table.
+    pub cost_usd: Option<f64>,
+}
+
+/// One row of `/api/usage`: LLM usage of a workflow with one model on one day.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UsageSummary {
+    pub workflow_id: String,
+    pub workflow_name: String,
+    pub model: String,
+    pub day: chrono::NaiveDate,
+    pub calls: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    /// `None` when none of the calls had a price.
+    pub cost_usd: Option<f64>,
+}
+
+/// Price of a model in USD per million tokens.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModelPrice {
+    pub model: String,
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeModuleRef {
+    pub name: String,
+    pub version: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -40,6 +123,11 @@ pub struct Workflow {
     pub description: String,
     pub nodes: serde_json::Value,
     pub edges: serde_json::Value,
+    #[serde(default)]
+    pub active: bool,
+    /// Workflow-level options such as `errorWorkflowId`.
+    #[serde(default)]
+    pub settings: serde_json::Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -101,6 +189,71 @@ pub struct DataTableRow {
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// A searchable collection of documents. Uploads are split into chunks of `chunk_size` characters
+/// (overlapping by `chunk_overlap`) and embedded with the configured provider and model.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct KnowledgeBase {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub embedding_provider: String,
+    pub embedding_model: String,
+    pub dimensions: Option<i32>,
+    pub chunk_size: i32,
+    pub chunk_overlap: i32,
+    pub credential_id: Option<Uuid>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// An uploaded file of a knowledge base. `status` is `processing` until its chunks are embedded,
+/// then `ready`, or `failed` with `error` set.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct KnowledgeDocument {
+    pub id: Uuid,
+    pub knowledge_base_id: Uuid,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub status: String,
+    pub error: Option<String>,
+    pub chunk_count: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A chunk returned by a knowledge base search, with its cosine similarity to the query.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct KnowledgeChunkMatch {
+    pub content: String,
+    pub score: f64,
+    pub document_id: Uuid,
+    pub document_name: String,
+    pub chunk_index: i32,
+}
+
+/// A reusable JS or Python module that Code nodes can import by name. Every update bumps `version`
+/// and keeps the previous source in `code_module_versions`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CodeModule {
+    pub id: Uuid,
+    pub name: String,
+    pub language: String,
+    pub description: Option<String>,
+    pub version: i32,
+    pub code: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CodeModuleVersion {
+    pub module_id: Uuid,
+    pub version: i32,
+    pub code: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnvVar {
     pub key: String,
@@ -114,6 +267,8 @@ pub struct WorkflowInput {
     pub description: String,
     pub nodes: Vec<Node>,
     pub edges: Vec<Edge>,
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -141,3 +296,29 @@ pub struct DataTableInput {
     pub description: Option<String>,
     pub schema: serde_json::Value,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct KnowledgeBaseInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub embedding_provider: Option<String>,
+    pub embedding_model: Option<String>,
+    pub dimensions: Option<i32>,
+    pub chunk_size: Option<i32>,
+    pub chunk_overlap: Option<i32>,
+    pub credential_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KnowledgeSearchInput {
+    pub query: String,
+    pub top_k: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CodeModuleInput {
+    pub name: String,
+    pub language: String,
+    pub description: Option<String>,
+    pub code: String,
+}
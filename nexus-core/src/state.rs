@@ -1,9 +1,8 @@
 use sqlx::{Pool, Postgres};
-use crate::clients::{OpenAiClient, OpenRouterClient};
+use crate::triggers::TriggerManager;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Pool<Postgres>,
-    pub openrouter: Option<OpenRouterClient>,
-    pub openai: Option<OpenAiClient>,
+    pub triggers: TriggerManager,
 }
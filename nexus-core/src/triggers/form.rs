@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::models::{Edge, NodeExecutionResult};
+
+const FIELD_TYPES: [&str; 5] = ["text", "number", "dropdown", "file", "date"];
+
+/// A single input declared on a `form-trigger` node.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FormField {
+    pub label: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default = "default_field_type", rename = "type")]
+    pub field_type: String,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub placeholder: Option<String>,
+}
+
+fn default_field_type() -> String {
+    "text".to_string()
+}
+
+impl FormField {
+    /// Key used for the field in the workflow input.
+    pub fn key(&self) -> String {
+        self.name.clone().filter(|n| !n.trim().is_empty()).unwrap_or_else(|| self.label.clone())
+    }
+}
+
+/// An uploaded file from a submitted form.
+#[derive(Debug, Clone)]
+pub struct UploadedFile {
+    pub file_name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Reads and checks the field declarations of a `form-trigger` node.
+pub fn fields(config: &serde_json::Value) -> Result<Vec<FormField>, String> {
+    let fields: Vec<FormField> = match config.get("fields") {
+        Some(v) => serde_json::from_value(v.clone()).map_err(|e| format!("Invalid form fields: {}", e))?,
+        None => Vec::new(),
+    };
+    if fields.is_empty() {
+        return Err("Form has no fields".to_string());
+    }
+    for field in &fields {
+        if field.label.trim().is_empty() {
+            return Err("Form field label not specified".to_string());
+        }
+        if !FIELD_TYPES.contains(&field.field_type.as_str()) {
+            return Err(format!("Field '{}' has unsupported type '{}'", field.label, field.field_type));
+        }
+        if field.field_type == "dropdown" && field.options.is_empty() {
+            return Err(format!("Dropdown field '{}' has no options", field.label));
+        }
+    }
+    Ok(fields)
+}
+
+/// Validates submitted values against the declared fields and builds the workflow input.
+/// Field inputs are posted as `field-<index>` so labels never need to be valid form names.
+pub fn validate_submission(
+    fields: &[FormField],
+    values: &HashMap<String, String>,
+    files: &HashMap<String, UploadedFile>,
+) -> Result<serde_json::Value, Vec<String>> {
+    use base64::{Engine as _, engine::general_purpose};
+
+    let mut output = serde_json::Map::new();
+    let mut errors = Vec::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        let input_name = format!("field-{}", i);
+        let raw = values.get(&input_name).map(|s| s.trim()).unwrap_or("");
+
+        let value = match field.field_type.as_str() {
+            "file" => match files.get(&input_name) {
+                Some(file) => serde_json::json!({
+                    "fileName": file.file_name,
+                    "mimeType": file.mime_type,
+                    "data": general_purpose::STANDARD.encode(&file.data),
+                    "format": "base64",
+                }),
+                None => serde_json::Value::Null,
+            },
+            _ if raw.is_empty() => serde_json::Value::Null,
+            "number" => match raw.parse::<f64>() {
+                Ok(n) => serde_json::json!(n),
+                Err(_) => {
+                    errors.push(format!("{} must be a number", field.label));
+                    continue;
+                }
+            },
+            "date" => match chrono::NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+                Ok(d) => serde_json::json!(d.format("%Y-%m-%d").to_string()),
+                Err(_) => {
+                    errors.push(format!("{} must be a date (YYYY-MM-DD)", field.label));
+                    continue;
+                }
+            },
+            "dropdown" => {
+                if !field.options.iter().any(|o| o == raw) {
+                    errors.push(format!("{} must be one of: {}", field.label, field.options.join(", ")));
+                    continue;
+                }
+                serde_json::json!(raw)
+            }
+            _ => serde_json::json!(raw),
+        };
+
+        if field.required && value.is_null() {
+            errors.push(format!("{} is required", field.label));
+            continue;
+        }
+        output.insert(field.key(), value);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    output.insert("submittedAt".to_string(), serde_json::json!(chrono::Utc::now().to_rfc3339()));
+    Ok(serde_json::Value::Object(output))
+}
+
+/// Renders the hosted HTML form, re-filling submitted values and listing validation errors.
+pub fn render_form(config: &serde_json::Value, fields: &[FormField], values: &HashMap<String, String>, errors: &[String]) -> String {
+    let title = config.get("formTitle").and_then(|v| v.as_str()).unwrap_or("Form");
+    let description = config.get("formDescription").and_then(|v| v.as_str()).unwrap_or("");
+    let button = config.get("buttonLabel").and_then(|v| v.as_str()).unwrap_or("Submit");
+
+    let mut body = String::new();
+    if !description.is_empty() {
+        body.push_str(&format!("<p class=\"description\">{}</p>", escape_html(description)));
+    }
+    if !errors.is_empty() {
+        body.push_str("<ul class=\"errors\">");
+        for error in errors {
+            body.push_str(&format!("<li>{}</li>", escape_html(error)));
+        }
+        body.push_str("</ul>");
+    }
+
+    body.push_str("<form method=\"post\" enctype=\"multipart/form-data\">");
+    for (i, field) in fields.iter().enumerate() {
+        let name = format!("field-{}", i);
+        let value = escape_html(values.get(&name).map(|s| s.as_str()).unwrap_or(""));
+        let required = if field.required { " required" } else { "" };
+        let placeholder = escape_html(field.placeholder.as_deref().unwrap_or(""));
+        let marker = if field.required { " *" } else { "" };
+        body.push_str(&format!("<label for=\"{}\">{}{}</label>", name, escape_html(&field.label), marker));
+
+        let input = match field.field_type.as_str() {
+            "dropdown" => {
+                let mut options = String::from("<option value=\"\"></option>");
+                for option in &field.options {
+                    let selected = if values.get(&name) == Some(option) { " selected" } else { "" };
+                    options.push_str(&format!("<option{}>{}</option>", selected, escape_html(option)));
+                }
+                format!("<select id=\"{0}\" name=\"{0}\"{1}>{2}</select>", name, required, options)
+            }
+            "file" => format!("<input type=\"file\" id=\"{0}\" name=\"{0}\"{1}>", name, required),
+            "number" => format!("<input type=\"number\" step=\"any\" id=\"{0}\" name=\"{0}\" value=\"{1}\" placeholder=\"{2}\"{3}>", name, value, placeholder, required),
+            "date" => format!("<input type=\"date\" id=\"{0}\" name=\"{0}\" value=\"{1}\"{2}>", name, value, required),
+            _ => format!("<input type=\"text\" id=\"{0}\" name=\"{0}\" value=\"{1}\" placeholder=\"{2}\"{3}>", name, value, placeholder, required),
+        };
+        body.push_str(&input);
+    }
+    body.push_str(&format!("<button type=\"submit\">{}</button></form>", escape_html(button)));
+
+    render_page(title, &body)
+}
+
+/// Picks the result a `lastNode` form answers with: the first failure if any node failed, otherwise the
+/// last node to run that has no outgoing edges. Results are in execution order, so with several branches
+/// this is whichever end of the workflow finished last.
+pub fn final_result<'a>(results: &'a [NodeExecutionResult], edges: &[Edge]) -> Option<&'a NodeExecutionResult> {
+    if let Some(failed) = results.iter().find(|r| !r.success) {
+        return Some(failed);
+    }
+    results.iter().rev().find(|r| !edges.iter().any(|e| e.from.to_string() == r.node_id))
+}
+
+/// Renders a plain result page, either a message or the workflow's final output.
+pub fn render_result(title: &str, output: &serde_json::Value) -> String {
+    let body = match output {
+        serde_json::Value::String(s) => format!("<p>{}</p>", escape_html(s)),
+        _ => match output.get("text").and_then(|v| v.as_str()) {
+            Some(text) => format!("<p>{}</p>", escape_html(text)),
+            None => format!("<pre>{}</pre>", escape_html(&serde_json::to_string_pretty(output).unwrap_or_default())),
+        },
+    };
+    render_page(title, &body)
+}
+
+fn render_page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{0}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; background: #f5f5f4; margin: 0; padding: 40px 16px; }}
+main {{ max-width: 520px; margin: 0 auto; background: #fff; border-radius: 12px; padding: 32px; box-shadow: 0 1px 3px rgba(0,0,0,.1); }}
+h1 {{ font-size: 1.4rem; margin-top: 0; }}
+label {{ display: block; font-weight: 600; margin: 16px 0 6px; }}
+input, select {{ width: 100%; box-sizing: border-box; padding: 8px 10px; border: 1px solid #d6d3d1; border-radius: 6px; font: inherit; }}
+button {{ margin-top: 24px; width: 100%; padding: 10px; border: 0; border-radius: 6px; background: #1c1917; color: #fff; font: inherit; cursor: pointer; }}
+.description {{ color: #57534e; }}
+.errors {{ color: #b91c1c; padding-left: 20px; }}
+pre {{ white-space: pre-wrap; word-break: break-word; background: #f5f5f4; padding: 12px; border-radius: 6px; }}
+</style>
+</head>
+<body><main><h1>{0}</h1>{1}</main></body>
+</html>"#,
+        escape_html(title),
+        body
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use base64::{Engine as _, engine::general_purpose};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::time::Instant;
+use super::TriggerContext;
+
+const RETRY_DELAY_SECS: u64 = 30;
+
+/// Files above this size are reported without their content.
+const DEFAULT_MAX_CONTENT_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Entry point for a `local-file-trigger` node. Re-creates the watcher after errors until the task is aborted.
+pub async fn run(ctx: TriggerContext) {
+    loop {
+        if let Err(e) = watch(&ctx).await {
+            eprintln!("local-file-trigger {} ({}): {}", ctx.node.label, ctx.workflow_name, e);
+        }
+        tokio::time::sleep(Duration::from_secs(RETRY_DELAY_SECS)).await;
+    }
+}
+
+async fn watch(ctx: &TriggerContext) -> Result<(), String> {
+    let config = &ctx.node.config;
+    let path = config.get("path").and_then(|v| v.as_str()).ok_or("Path not specified")?;
+    let recursive = config.get("recursive").and_then(|v| v.as_bool()).unwrap_or(false);
+    let debounce = Duration::from_millis(config.get("debounceMs").and_then(|v| v.as_u64()).unwrap_or(500));
+    let include_content = config.get("includeContent").and_then(|v| v.as_bool()).unwrap_or(false);
+    let max_content_bytes = config.get("maxContentBytes").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_MAX_CONTENT_BYTES);
+    let events = string_list(config.get("events"));
+    let ignore = string_list(config.get("ignore"))
+        .iter()
+        .map(|p| glob::Pattern::new(p).map_err(|e| format!("Invalid ignore pattern '{}': {}", p, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // A glob such as `/data/inbox/*.csv` is watched through its non-glob parent directory
+    let (watch_dir, filter) = split_glob(path)?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res| { let _ = tx.send(res); })
+        .map_err(|e| e.to_string())?;
+    let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    watcher.watch(&watch_dir, mode).map_err(|e| format!("Failed to watch {}: {}", watch_dir.display(), e))?;
+
+    let mut pending: HashMap<PathBuf, (&'static str, Instant)> = HashMap::new();
+    loop {
+        let next_due = pending.values().map(|(_, seen)| *seen + debounce).min();
+        tokio::select! {
+            msg = rx.recv() => {
+                let event = match msg {
+                    Some(Ok(event)) => event,
+                    Some(Err(e)) => return Err(e.to_string()),
+                    None => return Err("Watcher stopped".to_string()),
+                };
+                let Some(kind) = event_name(&event.kind) else { continue; };
+                for path in event.paths {
+                    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    if ignore.iter().any(|p| p.matches(&file_name) || p.matches_path(&path)) { continue; }
+                    if let Some(f) = &filter && !f.matches_path(&path) { continue; }
+
+                    // Collapse bursts: a create followed by writes is still a create, and a
+                    // file that is created and removed within the window is never reported
+                    match (pending.get(&path).map(|(k, _)| *k), kind) {
+                        (Some("created"), "deleted") => { pending.remove(&path); }
+                        (Some("created"), _) => { pending.insert(path, ("created", Instant::now())); }
+                        _ => { pending.insert(path, (kind, Instant::now())); }
+                    }
+                }
+            }
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                let now = Instant::now();
+                let due: Vec<PathBuf> = pending.iter()
+                    .filter(|(_, (_, seen))| *seen + debounce <= now)
+                    .map(|(p, _)| p.clone())
+                    .collect();
+                for path in due {
+                    let Some((kind, _)) = pending.remove(&path) else { continue; };
+                    if !events.is_empty() && !events.iter().any(|e| e == kind) { continue; }
+                    let content_limit = include_content.then_some(max_content_bytes);
+                    ctx.spawn_emit(build_event(&path, kind, content_limit).await);
+                }
+            }
+        }
+    }
+}
+
+fn event_name(kind: &EventKind) -> Option<&'static str> {
+    match kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Some("created"),
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Some("deleted"),
+        EventKind::Modify(ModifyKind::Metadata(_)) => None,
+        EventKind::Modify(_) => Some("modified"),
+        _ => None,
+    }
+}
+
+/// Describes a file event. With `content_limit` set, the file's content is attached when it is no larger.
+async fn build_event(path: &Path, kind: &str, content_limit: Option<u64>) -> serde_json::Value {
+    let mut event = serde_json::json!({
+        "event": kind,
+        "path": path.to_string_lossy(),
+        "fileName": path.file_name().map(|n| n.to_string_lossy().to_string()),
+    });
+
+    if let Some(limit) = content_limit && kind != "deleted" {
+        let obj = event.as_object_mut().unwrap();
+        let content = match tokio::fs::metadata(path).await {
+            Ok(meta) if meta.len() > limit => Err(format!("File is {} bytes, larger than the {} byte content limit", meta.len(), limit)),
+            Ok(_) => tokio::fs::read(path).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match content {
+            Ok(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => { obj.insert("data".to_string(), serde_json::json!(text)); }
+                Err(e) => {
+                    obj.insert("data".to_string(), serde_json::json!(general_purpose::STANDARD.encode(e.into_bytes())));
+                    obj.insert("format".to_string(), serde_json::json!("base64"));
+                }
+            },
+            Err(e) => { obj.insert("error".to_string(), serde_json::json!(e)); }
+        }
+    }
+
+    event
+}
+
+/// Splits `path` into the directory to watch and an optional glob the event paths must match.
+fn split_glob(path: &str) -> Result<(PathBuf, Option<glob::Pattern>), String> {
+    if !path.contains(['*', '?', '[']) {
+        return Ok((PathBuf::from(path), None));
+    }
+
+    let mut base = PathBuf::new();
+    for component in Path::new(path).components() {
+        if component.as_os_str().to_string_lossy().contains(['*', '?', '[']) { break; }
+        base.push(component);
+    }
+    if base.as_os_str().is_empty() { base.push("."); }
+
+    let pattern = glob::Pattern::new(path).map_err(|e| format!("Invalid path pattern: {}", e))?;
+    Ok((base, Some(pattern)))
+}
+
+/// Reads a config value given either as a JSON array or a comma-separated string.
+fn string_list(value: Option<&serde_json::Value>) -> Vec<String> {
+    match value {
+        Some(serde_json::Value::Array(arr)) => arr.iter().filter_map(|v| v.as_str()).map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        Some(serde_json::Value::String(s)) => s.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,184 @@
+pub mod form;
+pub mod local_file;
+pub mod postgres;
+pub mod schedule;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::models::{Node, Edge, NodeExecutionResult, Workflow};
+
+/// Keeps track of the schedulers, pollers, webhook and form paths registered for each active workflow.
+#[derive(Clone, Default)]
+pub struct TriggerManager {
+    tasks: Arc<Mutex<HashMap<Uuid, Vec<JoinHandle<()>>>>>,
+    webhooks: Arc<Mutex<HashMap<String, TriggerContext>>>,
+    forms: Arc<Mutex<HashMap<String, TriggerContext>>>,
+}
+
+/// Everything a trigger task needs to start executions of its workflow.
+#[derive(Clone)]
+pub struct TriggerContext {
+    pub db: Pool<Postgres>,
+    pub workflow_id: Uuid,
+    pub workflow_name: String,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub node: Node,
+}
+
+impl TriggerContext {
+    /// Runs one execution of the workflow with `event` as the trigger node's input.
+    pub async fn emit(&self, event: serde_json::Value) -> (Uuid, Vec<NodeExecutionResult>) {
+        crate::engine::run_workflow_from_node(&self.db, self.workflow_id, &self.workflow_name, &self.nodes, &self.edges, &self.node, event).await
+    }
+
+    /// Starts an execution in the background so a slow workflow doesn't hold up the trigger's event loop.
+    pub fn spawn_emit(&self, event: serde_json::Value) {
+        let ctx = self.clone();
+        tokio::spawn(async move { ctx.emit(event).await; });
+    }
+}
+
+/// Registers `ctx` under `path` unless another workflow holds it, whose name is returned instead.
+fn claim_path(paths: &Mutex<HashMap<String, TriggerContext>>, path: String, ctx: TriggerContext) -> Result<(), String> {
+    let mut paths = paths.lock().unwrap();
+    match paths.get(&path) {
+        Some(owner) if owner.workflow_id != ctx.workflow_id => Err(owner.workflow_name.clone()),
+        _ => {
+            paths.insert(path, ctx);
+            Ok(())
+        }
+    }
+}
+
+impl TriggerManager {
+    /// Stops anything registered for `workflow` and, if it is active, registers its trigger nodes again.
+    /// Webhook and form paths already owned by another workflow are left alone and reported.
+    pub fn sync_workflow(&self, db: &Pool<Postgres>, workflow: &Workflow) -> Vec<String> {
+        self.stop_workflow(workflow.id);
+        let mut conflicts = Vec::new();
+        if !workflow.active { return conflicts; }
+
+        let nodes: Vec<Node> = serde_json::from_value(workflow.nodes.clone()).unwrap_or_default();
+        let edges: Vec<Edge> = serde_json::from_value(workflow.edges.clone()).unwrap_or_default();
+
+        let mut handles = Vec::new();
+        for node in &nodes {
+            let ctx = TriggerContext {
+                db: db.clone(),
+                workflow_id: workflow.id,
+                workflow_name: workflow.name.clone(),
+                nodes: nodes.clone(),
+                edges: edges.clone(),
+                node: node.clone(),
+            };
+            match node.kind.as_str() {
+                "trigger-schedule" => handles.push(tokio::spawn(schedule::run(ctx))),
+                "trigger-webhook" => {
+                    if let Some(path) = webhook_path(&node.config) && let Err(owner) = claim_path(&self.webhooks, path.clone(), ctx) {
+                        conflicts.push(format!("{}: webhook path '{}' is already used by workflow '{}'", node.label, path, owner));
+                    }
+                }
+                "form-trigger" => {
+                    if let Some(path) = webhook_path(&node.config) && let Err(owner) = claim_path(&self.forms, path.clone(), ctx) {
+                        conflicts.push(format!("{}: form path '{}' is already used by workflow '{}'", node.label, path, owner));
+                    }
+                }
+                "postgres-trigger" => handles.push(tokio::spawn(postgres::run(ctx))),
+                "local-file-trigger" => handles.push(tokio::spawn(local_file::run(ctx))),
+                _ => {}
+            }
+        }
+
+        if !handles.is_empty() {
+            self.tasks.lock().unwrap().insert(workflow.id, handles);
+        }
+        conflicts
+    }
+
+    /// Aborts every trigger task and releases every webhook path that belongs to the given workflow.
+    pub fn stop_workflow(&self, workflow_id: Uuid) {
+        if let Some(handles) = self.tasks.lock().unwrap().remove(&workflow_id) {
+            for handle in handles {
+                handle.abort();
+            }
+        }
+        self.webhooks.lock().unwrap().retain(|_, ctx| ctx.workflow_id != workflow_id);
+        self.forms.lock().unwrap().retain(|_, ctx| ctx.workflow_id != workflow_id);
+    }
+
+    /// Looks up the webhook trigger registered under `path`.
+    pub fn webhook(&self, path: &str) -> Option<TriggerContext> {
+        self.webhooks.lock().unwrap().get(path.trim_matches('/')).cloned()
+    }
+
+    /// Looks up the form trigger registered under `path`.
+    pub fn form(&self, path: &str) -> Option<TriggerContext> {
+        self.forms.lock().unwrap().get(path.trim_matches('/')).cloned()
+    }
+
+    /// Checks that every trigger node in `workflow` is configured well enough to be activated.
+    pub fn validate_workflow(&self, workflow: &Workflow) -> Vec<String> {
+        let nodes: Vec<Node> = serde_json::from_value(workflow.nodes.clone()).unwrap_or_default();
+        let mut errors = Vec::new();
+
+        for node in &nodes {
+            let config = &node.config;
+            let has = |key: &str| config.get(key).and_then(|v| v.as_str()).is_some_and(|s| !s.trim().is_empty());
+            let mut missing = |key: &str, what: &str| {
+                if !has(key) { errors.push(format!("{}: {} not specified", node.label, what)); }
+            };
+
+            match node.kind.as_str() {
+                "trigger-schedule" if schedule::interval(config).is_none() => {
+                    errors.push(format!("{}: schedule interval must be at least one second", node.label));
+                }
+                "trigger-webhook" => match webhook_path(config) {
+                    Some(path) => {
+                        if let Some(other) = self.webhook(&path) && other.workflow_id != workflow.id {
+                            errors.push(format!("{}: webhook path '{}' is already used by workflow '{}'", node.label, path, other.workflow_name));
+                        }
+                    }
+                    None => errors.push(format!("{}: webhook path not specified", node.label)),
+                },
+                "form-trigger" => {
+                    match webhook_path(config) {
+                        Some(path) => {
+                            if let Some(other) = self.form(&path) && other.workflow_id != workflow.id {
+                                errors.push(format!("{}: form path '{}' is already used by workflow '{}'", node.label, path, other.workflow_name));
+                            }
+                        }
+                        None => errors.push(format!("{}: form path not specified", node.label)),
+                    }
+                    if let Err(e) = form::fields(config) {
+                        errors.push(format!("{}: {}", node.label, e));
+                    }
+                }
+                "slack-trigger" => missing("trigger", "Slack event"),
+                "postgres-trigger" => {
+                    missing("credentialId", "Credential");
+                    if config.get("mode").and_then(|v| v.as_str()) == Some("poll") {
+                        missing("table", "Table");
+                        missing("cursorColumn", "Cursor column");
+                    } else {
+                        missing("channel", "Channel");
+                    }
+                }
+                "local-file-trigger" => missing("path", "Path"),
+                _ => {}
+            }
+        }
+
+        errors
+    }
+}
+
+fn webhook_path(config: &serde_json::Value) -> Option<String> {
+    config.get("path")
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().trim_matches('/').to_string())
+        .filter(|s| !s.is_empty())
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+use sqlx::postgres::{PgListener, PgPoolOptions};
+use crate::engine::{postgres_credential_url, row_to_json};
+use super::TriggerContext;
+
+const RETRY_DELAY_SECS: u64 = 30;
+
+/// Column aliases used to read the cursor and key values back as text without clobbering user columns.
+const CURSOR_ALIAS: &str = "__nexus_cursor";
+const KEY_ALIAS: &str = "__nexus_key";
+
+#[derive(Default)]
+struct PollState {
+    seeded: bool,
+    cursor: Option<String>,
+    key: Option<String>,
+}
+
+/// Entry point for a `postgres-trigger` node. Reconnects after errors until the task is aborted.
+pub async fn run(ctx: TriggerContext) {
+    let mode = ctx.node.config.get("mode").and_then(|v| v.as_str()).unwrap_or("listen").to_string();
+    let mut state = PollState::default();
+
+    loop {
+        let result = if mode == "poll" { poll(&ctx, &mut state).await } else { listen(&ctx).await };
+        if let Err(e) = result {
+            eprintln!("postgres-trigger {} ({}): {}", ctx.node.label, ctx.workflow_name, e);
+        }
+        tokio::time::sleep(Duration::from_secs(RETRY_DELAY_SECS)).await;
+    }
+}
+
+/// LISTENs on a channel and emits every NOTIFY as an execution.
+async fn listen(ctx: &TriggerContext) -> Result<(), String> {
+    let cred_id = ctx.node.config.get("credentialId").and_then(|v| v.as_str()).ok_or("Credential not specified")?;
+    let channel = ctx.node.config.get("channel").and_then(|v| v.as_str()).ok_or("Channel not specified")?;
+
+    let connection_url = postgres_credential_url(&ctx.db, cred_id).await?;
+    let mut listener = PgListener::connect(&connection_url).await.map_err(|e| format!("Failed to connect to external Postgres: {}", e))?;
+    listener.listen(channel).await.map_err(|e| e.to_string())?;
+
+    loop {
+        let notification = listener.recv().await.map_err(|e| e.to_string())?;
+        let payload = serde_json::from_str::<serde_json::Value>(notification.payload())
+            .unwrap_or_else(|_| serde_json::json!(notification.payload()));
+        ctx.spawn_emit(serde_json::json!({
+            "channel": notification.channel(),
+            "payload": payload,
+            "processId": notification.process_id(),
+        }));
+    }
+}
+
+/// Polls a table for rows whose cursor column moved past the last seen value.
+/// The cursor must only ever increase. When it isn't unique (a timestamp, say), `keyColumn` names a unique column
+/// that orders rows sharing a cursor value, so a batch cut off by `limit` in the middle of them resumes where it stopped.
+async fn poll(ctx: &TriggerContext, state: &mut PollState) -> Result<(), String> {
+    let config = &ctx.node.config;
+    let cred_id = config.get("credentialId").and_then(|v| v.as_str()).ok_or("Credential not specified")?;
+    let schema = config.get("schema").and_then(|v| v.as_str()).unwrap_or("public");
+    let table = config.get("table").and_then(|v| v.as_str()).ok_or("Table not specified")?;
+    let cursor_column = config.get("cursorColumn").and_then(|v| v.as_str()).ok_or("Cursor column not specified")?;
+    let key_column = config.get("keyColumn").and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+    let poll_interval = config.get("pollInterval").and_then(|v| v.as_u64()).unwrap_or(60).max(1);
+    let limit = config.get("limit").and_then(|v| v.as_i64()).unwrap_or(100).max(1);
+    let emit_existing = config.get("emitExisting").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let connection_url = postgres_credential_url(&ctx.db, cred_id).await?;
+    let ext_pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&connection_url)
+        .await
+        .map_err(|e| format!("Failed to connect to external Postgres: {}", e))?;
+
+    let table_ref = format!("{}.{}", quote_ident(schema), quote_ident(table));
+    let cursor_type = column_type(&ext_pool, &table_ref, cursor_column).await?;
+    let key_type = match key_column {
+        Some(key) => Some(column_type(&ext_pool, &table_ref, key).await?),
+        None => None,
+    };
+    let col = quote_ident(cursor_column);
+
+    // Without a key column the key is always NULL
+    let (key, order, condition) = match (key_column, &key_type) {
+        (Some(key), Some(key_type)) => {
+            let key = quote_ident(key);
+            let condition = format!("({col}, {key}) > (CAST($1::text AS {cursor_type}), CAST($2::text AS {key_type}))");
+            (format!("{key}::text"), vec![col.clone(), key], condition)
+        }
+        _ => ("NULL::text".to_string(), vec![col.clone()], format!("{col} > CAST($1::text AS {cursor_type})")),
+    };
+    let order_by = |direction: &str| order.iter().map(|c| format!("{c} {direction}")).collect::<Vec<_>>().join(", ");
+
+    // Start from the current high-water mark so enabling the trigger doesn't replay the whole table
+    if !state.seeded {
+        if !emit_existing {
+            let last = sqlx::query_as::<_, (Option<String>, Option<String>)>(&format!("SELECT {col}::text, {key} FROM {table_ref} ORDER BY {} LIMIT 1", order_by("DESC NULLS LAST")))
+                .fetch_optional(&ext_pool)
+                .await
+                .map_err(|e| e.to_string())?;
+            (state.cursor, state.key) = last.unwrap_or_default();
+        }
+        state.seeded = true;
+    }
+
+    let query = format!(
+        "SELECT *, {col}::text AS {CURSOR_ALIAS}, {key} AS {KEY_ALIAS} FROM {table_ref} WHERE $1::text IS NULL OR {condition} ORDER BY {} LIMIT {limit}",
+        order_by("ASC")
+    );
+
+    let mut interval = tokio::time::interval(Duration::from_secs(poll_interval));
+    loop {
+        interval.tick().await;
+
+        let rows = sqlx::query(&query)
+            .bind(state.cursor.clone())
+            .bind(state.key.clone())
+            .fetch_all(&ext_pool)
+            .await
+            .map_err(|e: sqlx::Error| e.to_string())?;
+
+        for row in rows {
+            let mut event = row_to_json(&row);
+            if let Some(obj) = event.as_object_mut()
+                && let Some(cursor) = obj.remove(CURSOR_ALIAS).and_then(|v| v.as_str().map(|s| s.to_string()))
+            {
+                state.cursor = Some(cursor);
+                state.key = obj.remove(KEY_ALIAS).and_then(|v| v.as_str().map(|s| s.to_string()));
+            }
+            ctx.spawn_emit(event);
+        }
+    }
+}
+
+/// Type of `column` on `table_ref`, as written in a cast.
+async fn column_type(pool: &sqlx::PgPool, table_ref: &str, column: &str) -> Result<String, String> {
+    sqlx::query_scalar::<_, String>(
+        "SELECT format_type(atttypid, atttypmod) FROM pg_attribute WHERE attrelid = $1::regclass AND attname = $2 AND NOT attisdropped"
+    )
+    .bind(table_ref)
+    .bind(column)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or(format!("Column '{}' not found on {}", column, table_ref))
+}
+
+/// Quotes an identifier so names are used exactly as configured, mixed case included.
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
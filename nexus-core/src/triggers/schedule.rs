@@ -0,0 +1,29 @@
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+use super::TriggerContext;
+
+/// Entry point for a `trigger-schedule` node. Fires once per configured interval.
+pub async fn run(ctx: TriggerContext) {
+    let Some(period) = interval(&ctx.node.config) else { return; };
+
+    let mut ticker = tokio::time::interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        ctx.emit(serde_json::json!({ "timestamp": chrono::Utc::now().to_rfc3339() })).await;
+    }
+}
+
+/// Reads `interval` + `unit` (seconds, minutes, hours, days) from the node config.
+pub fn interval(config: &serde_json::Value) -> Option<Duration> {
+    let amount = config.get("interval").and_then(|v| v.as_f64()).unwrap_or(1.0);
+    let unit = config.get("unit").and_then(|v| v.as_str()).unwrap_or("minutes");
+    let seconds = match unit {
+        "seconds" => amount,
+        "minutes" => amount * 60.0,
+        "hours" => amount * 3600.0,
+        "days" => amount * 86400.0,
+        _ => return None,
+    };
+    if seconds >= 1.0 { Some(Duration::from_secs_f64(seconds)) } else { None }
+}